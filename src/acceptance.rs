@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+
+use crate::logistic_regression::*;
+
+/// Rules a run has to meet before its results can be reported
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AcceptanceCriteria {
+    pub recovery: f64,      // backfit of a standard within 100 +- recovery %
+    pub lloq_recovery: f64, // the same for the standard at the LLOQ
+    pub cv: f64,            // %CV of the backfit replicates of a standard below this
+    pub r_squared: f64,     // of the fit, at least this
+    pub passing: f64,       // % of the standards that have to pass
+    #[serde(default)]
+    pub quality_control: QualityControlRule,
+}
+
+/// Run acceptance by the quality controls, like the 4-6-20 rule:
+/// 4 of 6 within 20% of their nominal concentration, and half of every level
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QualityControlRule {
+    pub passing: f64,   // % of all quality control wells
+    pub per_level: f64, // % of the wells of every level
+    pub tolerance: f64, // % around the nominal concentration, unless the level has its own
+}
+
+impl Default for QualityControlRule {
+    fn default() -> Self {
+        QualityControlRule::FOUR_SIX_TWENTY
+    }
+}
+
+impl QualityControlRule {
+    pub const FOUR_SIX_TWENTY: Self = Self { passing: 200.0 / 3.0, per_level: 50.0, tolerance: 20.0 };
+    pub const FOUR_SIX_FIFTEEN: Self = Self { passing: 200.0 / 3.0, per_level: 50.0, tolerance: 15.0 };
+
+    pub fn name(&self) -> String {
+        let Self { passing, per_level, tolerance } = *self;
+        if *self == QualityControlRule::FOUR_SIX_TWENTY {
+            String::from("4-6-20")
+        } else if *self == QualityControlRule::FOUR_SIX_FIFTEEN {
+            String::from("4-6-15")
+        } else {
+            format!("{passing:.0}% of QCs and {per_level:.0}% per level within {tolerance}%")
+        }
+    }
+}
+
+impl Default for AcceptanceCriteria {
+    fn default() -> Self {
+        Self {
+            recovery: 20.0,
+            lloq_recovery: 25.0,
+            cv: 20.0,
+            r_squared: 0.98,
+            passing: 75.0,
+            quality_control: QualityControlRule::default(),
+        }
+    }
+}
+
+impl AcceptanceCriteria {
+    pub fn description(&self) -> String {
+        format!(
+            "Recovery 100 +- {}% (+- {}% at LLOQ), CV < {}%, R^2 >= {}, {}% of standards",
+            self.recovery, self.lloq_recovery, self.cv, self.r_squared, self.passing
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StandardCheck {
+    pub concentration: f64,
+    pub backfit: f64,   // mean of the backfit replicates
+    pub recovery: f64,  // %
+    pub cv: f64,
+    pub at_lloq: bool,
+    pub passed: bool,
+}
+
+/// Whether a run is accepted, and the rules it broke if not
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub passed: bool,
+    pub failures: Vec<String>,
+}
+
+impl Verdict {
+    pub fn description(&self) -> String {
+        if self.passed {
+            String::from("Run passed")
+        } else {
+            format!("Run failed: {}", self.failures.join(", "))
+        }
+    }
+}
+
+/// Every backfitted well of a quality control level against its limits
+#[derive(Clone, Debug)]
+pub struct QualityControlCheck {
+    pub label: String,
+    pub nominal: f64,
+    pub limits: (f64, f64),
+    pub backfits: Vec<f64>,
+    pub passed: Vec<bool>,
+}
+
+impl QualityControlCheck {
+    pub fn passing(&self) -> usize {
+        self.passed.iter().filter(|&&passed| passed).count()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Acceptance {
+    pub criteria: AcceptanceCriteria,
+    pub standards: Vec<StandardCheck>,
+    pub quality_controls: Vec<QualityControlCheck>,
+    pub verdict: Verdict,
+}
+
+impl Regression {
+    pub fn check_acceptance(&self, criteria: AcceptanceCriteria) -> Acceptance {
+        // The standard at the LLOQ is the lowest one that can be quantified, or the lowest one at all
+        let lloq = self.limits.lloq;
+        let lloq_standard = self.standards.iter()
+            .map(|&(x, _y)| x)
+            .find(|&x| lloq.is_nan() || x >= lloq);
+
+        let standards: Vec<_> = self.standard_precision().into_iter().map(|(concentration, recovery, cv)| {
+            let at_lloq = Some(concentration) == lloq_standard;
+            let allowed = if at_lloq { criteria.lloq_recovery } else { criteria.recovery };
+            StandardCheck {
+                concentration,
+                backfit: concentration * recovery / 100.0,
+                recovery,
+                cv,
+                at_lloq,
+                // NaN fails too, the standard could not be backfitted
+                passed: (recovery - 100.0).abs() <= allowed && cv < criteria.cv,
+            }
+        }).collect();
+
+        let mut failures = Vec::new();
+        let passing = standards.iter().filter(|standard| standard.passed).count();
+        if (passing as f64) < criteria.passing / 100.0 * standards.len() as f64 {
+            failures.push(format!("{passing} of {} standards passed", standards.len()));
+        }
+        if self.r_sq.is_nan() || self.r_sq < criteria.r_squared {
+            failures.push(format!("R^2 {:.4} < {}", self.r_sq, criteria.r_squared));
+        }
+
+        let rule = criteria.quality_control;
+        let quality_controls: Vec<_> = self.quality_controls.iter().map(|quality_control| {
+            let QualityControlSample { label, nominal, tolerance, backfits, .. } = quality_control;
+            let tolerance = tolerance.unwrap_or(rule.tolerance) / 100.0;
+            let limits = (nominal * (1.0 - tolerance), nominal * (1.0 + tolerance));
+            QualityControlCheck {
+                label: label.clone(),
+                nominal: *nominal,
+                limits,
+                backfits: backfits.clone(),
+                passed: backfits.iter().map(|backfit| (limits.0..=limits.1).contains(backfit)).collect(),
+            }
+        }).collect();
+
+        // Without quality controls there is nothing to judge
+        let wells: usize = quality_controls.iter().map(|level| level.passed.len()).sum();
+        let passing: usize = quality_controls.iter().map(QualityControlCheck::passing).sum();
+        if wells > 0 && (passing as f64) < rule.passing / 100.0 * wells as f64 {
+            failures.push(format!("{passing} of {wells} QCs within limits"));
+        }
+        for level in &quality_controls {
+            if (level.passing() as f64) < rule.per_level / 100.0 * level.passed.len() as f64 {
+                failures.push(format!("{} {} of {} within limits", level.label, level.passing(), level.passed.len()));
+            }
+        }
+
+        Acceptance {
+            criteria,
+            standards,
+            quality_controls,
+            verdict: Verdict { passed: failures.is_empty(), failures },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default;
+    use crate::limits::Limits;
+
+    // A line through the origin with slope 1, every response backfits to itself
+    fn regression(replicates: &[(f64, f64)], lloq: f64) -> Regression {
+        Regression {
+            model: Model::Linear,
+            coefficients: vec![0.0, 1.0],
+            standards: replicates.chunk_by(|a, b| a.0 == b.0).map(|group| (group[0].0, group[0].1)).collect(),
+            replicates: replicates.to_vec(),
+            r_sq: 0.99,
+            limits: Limits { lloq, ..default() },
+            ..default()
+        }
+    }
+
+    #[test]
+    fn standards_at_the_lloq_get_their_own_tolerance() {
+        let replicates = [
+            (10.0, 12.2), (10.0, 12.2), // 122% recovery
+            (20.0, 24.4), (20.0, 24.4), // 122% recovery
+            (40.0, 36.0), (40.0, 44.0), // 14% CV
+            (80.0, 64.0), (80.0, 96.0), // 28% CV
+        ];
+        let acceptance = regression(&replicates, 10.0).check_acceptance(default());
+        let passed: Vec<_> = acceptance.standards.iter().map(|standard| standard.passed).collect();
+        let at_lloq: Vec<_> = acceptance.standards.iter().map(|standard| standard.at_lloq).collect();
+        assert_eq!(passed, [true, false, true, false]);
+        assert_eq!(at_lloq, [true, false, false, false]);
+        assert_eq!(acceptance.verdict.failures, ["2 of 4 standards passed"]);
+
+        // With the LLOQ at the second standard the first one has to meet the usual tolerance
+        let acceptance = regression(&replicates, 15.0).check_acceptance(default());
+        let passed: Vec<_> = acceptance.standards.iter().map(|standard| standard.passed).collect();
+        assert_eq!(passed, [false, true, true, false]);
+
+        let criteria = AcceptanceCriteria { passing: 50.0, ..default() };
+        assert!(regression(&replicates, 10.0).check_acceptance(criteria).verdict.passed);
+    }
+
+    #[test]
+    fn four_of_six_quality_controls_and_half_of_every_level() {
+        let level = |label: &str, nominal: f64, backfits: &[f64]| QualityControlSample {
+            label: String::from(label),
+            nominal,
+            backfits: backfits.to_vec(),
+            ..default()
+        };
+        let check = |low: &[f64], high: &[f64]| {
+            let mut regression = regression(&[(10.0, 10.0), (10.0, 10.0), (100.0, 100.0), (100.0, 100.0)], 10.0);
+            regression.quality_controls = vec![level("Low", 10.0, low), level("High", 100.0, high)];
+            regression.check_acceptance(default()).verdict
+        };
+
+        assert!(check(&[9.0, 11.5, 13.0], &[100.0, 110.0, 130.0]).passed);
+        assert_eq!(check(&[9.0, 11.5, 13.0], &[100.0, 125.0, 130.0]).failures, ["3 of 6 QCs within limits", "High 1 of 3 within limits"]);
+        assert_eq!(check(&[9.0, 11.0, 10.0], &[100.0, 125.0, 130.0]).failures, ["High 1 of 3 within limits"]);
+    }
+}
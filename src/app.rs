@@ -1,10 +1,17 @@
 use crate::*;
+use super::history::*;
+use super::import::*;
+use super::layout::*;
 use super::logistic_regression::*;
+use super::outliers::*;
+use super::project::*;
+use super::readers::*;
+use super::templates::*;
 
-use calamine::Xlsx;
-use eframe::{egui::{self, pos2, vec2, Button, Color32, Context, FontData, FontDefinitions, FontFamily, Id, Margin, Modal, OpenUrl, Rect, Response, RichText, Shadow, Shape, Style, Theme, Ui, Vec2}, CreationContext};
+use calamine::{Data, Range, Xlsx};
+use eframe::{egui::{self, pos2, vec2, Button, Color32, Context, FontData, FontDefinitions, FontFamily, Id, Key, KeyboardShortcut, Label, Margin, Modifiers, Modal, OpenUrl, Rect, Response, RichText, Shadow, Shape, Style, Theme, Ui, Vec2}, CreationContext};
 use font_loader::system_fonts;
-use std::{fs::File, io::{BufReader, Read, Write}, sync::Arc};
+use std::{fs::File, io::{BufReader, Read, Write}, sync::{atomic::{AtomicUsize, Ordering}, Arc}, thread::{self, JoinHandle}};
 
 #[derive(Default, PartialEq)]
 pub enum ElisaTab {
@@ -19,6 +26,20 @@ pub enum SerdeError {
     CantReadFile,
     CantWriteFile,
     CantDeserialize,
+    CantReadSheet,
+}
+
+/// Bootstrap of the parameter intervals of the current result, running on its own thread
+pub struct BootstrapJob {
+    pub progress: Arc<AtomicUsize>, // finished resamples
+    pub resamples: usize,
+    handle: JoinHandle<ParameterIntervals>,
+}
+
+impl BootstrapJob {
+    pub fn description(&self) -> String {
+        format!("Bootstrap running, {} of {} resamples", self.progress.load(Ordering::Relaxed), self.resamples)
+    }
 }
 
 fn setup_fonts(context: &Context) {
@@ -97,15 +118,41 @@ pub struct Elisa {
     pub excel: Option<Xlsx<BufReader<File>>>,
     pub pdf_report: bool,
     pub plot_response: Option<Response>,
-    pub plot_parameters: Option<[(&'static str, f64); 9]>,
+    pub plot_parameters: Option<Vec<(&'static str, f64)>>,
     pub sheet_names: Vec<String>,
     pub regression: Option<Regression>,
     pub selected_sheet: usize,
-    pub selected_sample: Option<usize>,
+    pub selected_samples: Vec<usize>, // the last one is shown in the sample menu
+    pub drag_start: Option<usize>,
+    pub drag_base: Vec<usize>, // selection a drag with ctrl adds to
+    pub paint_mode: bool,
+    pub brush: (SampleType, usize), // type and group painted onto the wells
     pub selected_sample_group: usize,
     pub standards_textfield: Vec<String>,
     pub serde_error_modal: Option<SerdeError>,
     pub value_error_modal: Option<ValueError>,
+    pub fit_options_modal: bool,
+    pub outlier_modal: bool,
+    pub outlier_test: OutlierTest,
+    pub outlier_suggestions: Option<Vec<Suggestion>>, // None until a test has run
+    pub project: Project,      // its entry of the current plate is only updated on saving, switching and calculating
+    pub current_plate: usize,
+    pub project_modal: bool,
+    pub plate_results: Vec<Result<Regression, ValueError>>, // of every plate in the last calculation
+    pub template_modal: bool,
+    pub templates: Vec<Template>, // the library, read when it is opened
+    pub template_name: String,
+    pub layout_modal: bool,
+    pub layout_wizard: LayoutWizard,
+    pub import_modal: bool,
+    pub import_sheet: Option<Range<Data>>, // the selected sheet, read once when it is picked
+    pub import_range: String,
+    pub import_info: ReadInfo, // of an export of a known reader
+    pub import_profiles: Vec<ImportProfile>,
+    pub import_profile_name: String,
+    pub history: History<Snapshot>,
+    pub interacting: bool, // pointer or focus was held in the last frame
+    pub bootstrap: Option<BootstrapJob>,
 }
 
 impl Elisa {
@@ -118,21 +165,53 @@ impl Elisa {
 
         let width = 12;
         let height = 8;
-        let max_groups = 100;
-        Self {
-            microplate: Microplate::new(width, height),
-            standards_textfield: vec![String::new(); max_groups],
+        let microplate = Microplate::new(width, height);
+        let mut elisa = Self {
+            project: Project::new(microplate.clone()),
+            microplate,
             ..default()
-        }
+        };
+        elisa.history.rebase(elisa.snapshot());
+        elisa
     }
 }
 
 impl eframe::App for Elisa {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Text fields have their own undo while they are focused
+        let focused = ctx.memory(|memory| memory.focused().is_some());
+        if self.current_tab == ElisaTab::Edit && !focused {
+            let redo = KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z);
+            let undo = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+            // Ctrl+Z also matches with shift held, so redo goes first
+            if ctx.input_mut(|i| i.consume_shortcut(&redo)) {
+                if let Some(snapshot) = self.history.redo() { self.restore(snapshot) }
+            } else if ctx.input_mut(|i| i.consume_shortcut(&undo)) {
+                if let Some(snapshot) = self.history.undo() { self.restore(snapshot) }
+            }
+        }
+
+        if let Some(job) = &self.bootstrap {
+            if job.handle.is_finished() {
+                self.finish_bootstrap();
+            } else {
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            }
+        }
+
         match self.current_tab {
             ElisaTab::Edit => self.assay_edit(ctx),
             ElisaTab::Result => self.assay_result(ctx),
         }
+
+        // An edit is finished once the pointer is released and no text field has focus, drags and bulk edits are one step.
+        // Nothing can change while the app is idle, so the state is only compared when an interaction ends.
+        let idle = !ctx.input(|i| i.pointer.any_down()) && ctx.memory(|memory| memory.focused().is_none());
+        // An edit outdates the results of every plate, until they are calculated again
+        if idle && self.interacting && self.history.record(self.snapshot()) && self.current_tab == ElisaTab::Edit {
+            self.plate_results.clear();
+        }
+        self.interacting = !idle;
     }
 }
 
@@ -141,6 +220,13 @@ impl Elisa {
         let white = Color32::from_hex("#FBFBFE").unwrap();
         egui::CentralPanel::default().frame(egui::Frame::default().inner_margin(0.0).fill(white)).show(ctx, |ui| {
             let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+            self.fit_options(ui);
+            self.outlier_finder(ui);
+            self.project_settings(ui);
+            self.template_library(ui);
+            self.layout_wizard(ui);
+            self.import_dialog(ui);
+            // Errors of the dialogs go on top of them
             self.show_modals(ui);
 
             ui.painter().hline(0.0..=ui.max_rect().width(), 30.0, stroke);
@@ -199,6 +285,7 @@ impl Elisa {
 
         egui::CentralPanel::default().frame(egui::Frame::default().inner_margin(0.0).fill(white)).show(ctx, |ui| {
             let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+            self.show_modals(ui);
 
             ui.painter().hline(0.0..=ui.max_rect().width(), 30.0, stroke);
             ui.painter().vline(30.0, 0.0..=ui.max_rect().height(), stroke);
@@ -208,7 +295,23 @@ impl Elisa {
                 .show(ui, |ui| {
                     ui.vertical(|ui| {
                         ui.horizontal(|ui| {
-                            self.plot(ui);
+                            ui.vertical(|ui| {
+                                self.plot(ui);
+                                ui.add_space(30.0);
+                                egui::ScrollArea::vertical().id_salt("Result details").show(ui, |ui| {
+                                    if self.project.plates.len() > 1 {
+                                        self.combined_results(ui);
+                                        ui.add_space(30.0);
+                                    }
+                                    self.standards_acceptance(ui);
+                                    ui.add_space(30.0);
+                                    self.model_comparison(ui);
+                                    if self.regression.as_ref().is_some_and(|regression| !regression.potencies.is_empty()) {
+                                        ui.add_space(30.0);
+                                        self.relative_potency(ui);
+                                    }
+                                });
+                            });
                             ui.add_space(30.0);
                             ui.vertical(|ui| {
                                 self.plot_parameters(ui);
@@ -259,10 +362,11 @@ impl Elisa {
             if button.clicked() {
                 if let Some(path) = rfd::FileDialog::new()
                     .add_filter("Text", &["json"])
-                    .set_file_name("Assay")
+                    .set_file_name(if self.project.name.is_empty() { "Assay" } else { &self.project.name })
                     .save_file() {
                     if let Ok(mut file) = File::create(path) {
-                        let serialized = serde_json::to_string(&self.microplate).unwrap();
+                        self.store_plate();
+                        let serialized = serde_json::to_string(&self.project).unwrap();
                         if file.write_all(serialized.as_bytes()).is_err() {
                             self.serde_error_modal = Some(CantWriteFile);
                         }
@@ -272,6 +376,31 @@ impl Elisa {
                 }
             }
 
+            let rect = Rect::from_min_size(pos2(45.0 + 2.0 * (50.0 + 10.0), 5.0), vec2(60.0, 20.0));
+            let button = ui.put(rect, Button::new(RichText::new("Project").size(13.5)));
+            Self::dashed_outline(ui, &button);
+            if button.clicked() {
+                self.project_modal = true;
+            }
+
+            // Previous and next plate
+            let plates = self.project.plates.len();
+            let x = 45.0 + 2.0 * (50.0 + 10.0) + 60.0 + 20.0;
+            let rect = Rect::from_min_size(pos2(x, 5.0), vec2(20.0, 20.0));
+            let button = ui.put(rect, Button::new(RichText::new("<").size(13.5)));
+            Self::dashed_outline(ui, &button);
+            if button.clicked() && self.current_plate > 0 {
+                self.switch_plate(self.current_plate - 1);
+            }
+            let rect = Rect::from_min_size(pos2(x + 25.0, 5.0), vec2(120.0, 20.0));
+            ui.put(rect, Label::new(format!("{} ({} of {plates})", self.project.plate_name(self.current_plate), self.current_plate + 1)));
+            let rect = Rect::from_min_size(pos2(x + 150.0, 5.0), vec2(20.0, 20.0));
+            let button = ui.put(rect, Button::new(RichText::new(">").size(13.5)));
+            Self::dashed_outline(ui, &button);
+            if button.clicked() && self.current_plate + 1 < plates {
+                self.switch_plate(self.current_plate + 1);
+            }
+
             let rect = Rect::from_min_size(pos2(45.0 + 50.0 + 10.0, 5.0), vec2(50.0, 20.0));
             let button = ui.put(rect, Button::new(RichText::new("Load").size(13.5)));
             Self::dashed_outline(ui, &button);
@@ -284,8 +413,12 @@ impl Elisa {
                         if file.read_to_end(&mut buf).is_err() {
                             self.serde_error_modal = Some(CantReadFile);                                
                         }
-                        if let Ok(microplate) = serde_json::from_slice::<Microplate>(&buf) {
-                            self.microplate = microplate;
+                        if let Some(project) = Project::from_json(&buf) {
+                            // Loading is a step of the history like any edit
+                            self.history.record(self.snapshot());
+                            self.project = project;
+                            self.plate_results.clear();
+                            self.show_plate(0);
                         } else {
                             self.serde_error_modal = Some(CantDeserialize);
                         }
@@ -297,6 +430,79 @@ impl Elisa {
         });
     }
     
+    /// Writes the edited plate back into the project
+    pub fn store_plate(&mut self) {
+        self.project.plates[self.current_plate] = self.microplate.clone();
+    }
+
+    /// Switching is not a step of the history, the edits before it are
+    pub fn switch_plate(&mut self, index: usize) {
+        self.store_plate();
+        self.history.record(self.snapshot());
+        self.show_plate(index);
+        self.history.rebase(self.snapshot());
+    }
+
+    /// Shows a plate of the project without storing the edited one
+    pub fn show_plate(&mut self, index: usize) {
+        self.current_plate = index;
+        self.microplate = self.project.plates[index].clone();
+        self.regression = None;
+        self.bootstrap = None;
+        self.selected_samples.clear();
+        self.outlier_suggestions = None;
+        self.standards_textfield.iter_mut().for_each(String::clear);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let mut project = self.project.clone();
+        project.plates[self.current_plate] = self.microplate.clone();
+        Snapshot { project, current_plate: self.current_plate }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.project = snapshot.project;
+        self.plate_results.clear();
+        self.show_plate(snapshot.current_plate);
+    }
+
+    /// Fits every plate of the project, the current one becomes the result
+    pub fn calculate(&mut self) -> Result<(), ValueError> {
+        self.store_plate();
+        let results = self.project.regressions();
+        for (plate, result) in self.project.plates.iter_mut().zip(&results) {
+            if let Ok(regression) = result {
+                plate.verdict = Some(regression.acceptance.verdict.clone());
+            }
+        }
+        self.microplate.verdict = self.project.plates[self.current_plate].verdict.clone();
+        let current = results[self.current_plate].clone();
+        self.plate_results = results;
+
+        let regression = current?;
+        self.bootstrap = match regression.options.intervals {
+            IntervalMethod::Bootstrap { resamples, seed } => {
+                let progress = Arc::new(AtomicUsize::new(0));
+                let (counter, fit) = (progress.clone(), regression.clone());
+                let handle = thread::spawn(move || fit.bootstrap_intervals(resamples, seed, &counter));
+                Some(BootstrapJob { progress, resamples, handle })
+            }
+            IntervalMethod::Asymptotic => None,
+        };
+        self.regression = Some(regression);
+        Ok(())
+    }
+
+    // A job of an older result is dropped with it, its thread finishes unseen
+    fn finish_bootstrap(&mut self) {
+        let Some(job) = self.bootstrap.take() else { return };
+        let Ok((standard_errors, confidence_intervals)) = job.handle.join() else { return };
+        if let Some(regression) = &mut self.regression {
+            regression.standard_errors = standard_errors;
+            regression.confidence_intervals = confidence_intervals;
+        }
+    }
+
     fn show_modals(&mut self, ui: &mut Ui) {
         use SerdeError::*;
 
@@ -309,6 +515,7 @@ impl Elisa {
                         CantReadFile => "Could not read contents of the file",
                         CantWriteFile => "Could not write contents to the file",
                         CantDeserialize => "Could not load microplate from contents",
+                        CantReadSheet => "Could not read the sheet of the spreadsheet",
                     };
                     ui.label(format!("{}\nPlease try a different file.", label));
                     ui.add_space(10.0);
@@ -331,9 +538,11 @@ impl Elisa {
                         UnassignedValue => "Microplate has a sample without a value.",
                         InvalidConcentration => "Microplate has a standard sample with an invalid concentration.",
                         InvalidValue => "Microplate has a sample an invalid value.",
-                        NotEnoughStandards => "Microplate does not have enough standards for four parameter analysis.",
+                        NotEnoughStandards => "Microplate does not have enough standards for the selected model.",
                         BlankTooBig => "The blank is greater than one of the standard measurements",
                         ControlTooBig => "The control is greater than one of the standard measurements",
+                        ControlTooSmall => "The control is smaller than one of the standard measurements of a decreasing curve",
+                        IncompatibleUnits => "A standard has a unit that does not convert to the unit of the microplate, mass and molar units need a molecular weight.",
                     };
                     ui.label(text);
                     ui.add_space(10.0);
@@ -0,0 +1,107 @@
+use crate::logistic_regression::*;
+
+/// One sample measured at several dilutions, the unknown groups share its label.
+/// Only the dilutions within the working range count towards the result.
+#[derive(Clone, Debug)]
+pub struct DilutionSeries {
+    pub label: String,
+    pub concentration: f64, // mean in the original sample, NaN if no dilution is in range
+    pub sd: f64,
+    pub cv: f64,
+    pub in_range: Vec<f64>,     // dilutions
+    pub out_of_range: Vec<f64>, // dilutions, their unknowns are flagged
+}
+
+impl DilutionSeries {
+    pub fn description(&self) -> String {
+        let dilutions = self.in_range.len() + self.out_of_range.len();
+        format!(
+            "{}: {:.4} (SD {:.4}, %CV {:.1}), {} of {dilutions} dilutions in range",
+            self.label, self.concentration, self.sd, self.cv, self.in_range.len()
+        )
+    }
+}
+
+impl Regression {
+    /// Series need at least two different dilutions, labelled groups without dilutions are left alone
+    pub fn aggregate_dilutions(&self) -> Vec<DilutionSeries> {
+        let mut labels: Vec<&str> = Vec::new();
+        for unknown in &self.unknowns {
+            if !unknown.label.is_empty() && !labels.contains(&unknown.label.as_str()) {
+                labels.push(&unknown.label);
+            }
+        }
+
+        labels.into_iter().filter_map(|label| {
+            let unknowns: Vec<_> = self.unknowns.iter().filter(|unknown| unknown.label == label).collect();
+            if !unknowns.iter().any(|unknown| unknown.dilution != unknowns[0].dilution) { return None }
+
+            let (in_range, out_of_range): (Vec<_>, Vec<_>) = unknowns.into_iter().partition(|unknown| unknown.flag.is_none());
+            let concentrations: Vec<_> = in_range.iter().map(|unknown| unknown.concentration).collect();
+            let (concentration, sd) = if concentrations.is_empty() { (f64::NAN, f64::NAN) } else { mean_and_sd(&concentrations) };
+
+            Some(DilutionSeries {
+                label: label.to_owned(),
+                concentration,
+                sd,
+                cv: sd / concentration * 100.0,
+                in_range: in_range.iter().map(|unknown| unknown.dilution).collect(),
+                out_of_range: out_of_range.iter().map(|unknown| unknown.dilution).collect(),
+            })
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::RangeFlag;
+
+    fn unknown(label: &str, dilution: f64, concentration: f64, flag: Option<RangeFlag>) -> Unknown {
+        Unknown { label: label.to_owned(), dilution, concentration, flag, ..Default::default() }
+    }
+
+    #[test]
+    fn averages_the_dilutions_in_range() {
+        let regression = Regression {
+            unknowns: vec![
+                unknown("Serum", 1.0, 900.0, Some(RangeFlag::AboveUloq)),
+                unknown("Serum", 10.0, 1000.0, None),
+                unknown("Serum", 100.0, 1100.0, None),
+                unknown("Serum", 1000.0, f64::NAN, Some(RangeFlag::BelowLloq)),
+            ],
+            ..Default::default()
+        };
+        let [series] = regression.aggregate_dilutions().try_into().unwrap();
+        assert_eq!(series.label, "Serum");
+        assert!((series.concentration - 1050.0).abs() < 1e-9);
+        assert!((series.sd - 50f64 * 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(series.in_range, [10.0, 100.0]);
+        assert_eq!(series.out_of_range, [1.0, 1000.0]);
+    }
+
+    #[test]
+    fn series_without_a_dilution_in_range_has_no_concentration() {
+        let regression = Regression {
+            unknowns: vec![unknown("A", 1.0, 5.0, Some(RangeFlag::AboveUloq)), unknown("A", 2.0, 4.0, Some(RangeFlag::AboveUloq))],
+            ..Default::default()
+        };
+        let [series] = regression.aggregate_dilutions().try_into().unwrap();
+        assert!(series.concentration.is_nan());
+        assert!(series.in_range.is_empty());
+    }
+
+    #[test]
+    fn needs_a_label_and_two_dilutions() {
+        let regression = Regression {
+            unknowns: vec![
+                unknown("", 1.0, 5.0, None),
+                unknown("", 10.0, 5.0, None),
+                unknown("B", 10.0, 5.0, None),
+                unknown("B", 10.0, 6.0, None),
+            ],
+            ..Default::default()
+        };
+        assert!(regression.aggregate_dilutions().is_empty());
+    }
+}
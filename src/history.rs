@@ -0,0 +1,127 @@
+use crate::project::*;
+
+/// The whole document, with the edited plate in its project
+#[derive(Clone)]
+pub struct Snapshot {
+    pub project: Project,
+    pub current_plate: usize,
+}
+
+// Compared in serialized form, where NaN equals NaN. A NaN measurement would otherwise make every state a new one.
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.current_plate == other.current_plate && serde_json::to_string(&self.project).ok() == serde_json::to_string(&other.project).ok()
+    }
+}
+
+/// Undo and redo over whole states, every finished edit is one step
+pub struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    current: Option<T>, // the last recorded state
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self { undo: Vec::new(), redo: Vec::new(), current: None }
+    }
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    const LIMIT: usize = 100;
+
+    /// The previous state becomes an undo step if the state changed, returns whether it did
+    pub fn record(&mut self, state: T) -> bool {
+        if self.current.as_ref() == Some(&state) { return false }
+        if let Some(previous) = self.current.replace(state) {
+            self.undo.push(previous);
+            if self.undo.len() > Self::LIMIT {
+                self.undo.remove(0);
+            }
+            self.redo.clear();
+        }
+        true
+    }
+
+    /// Takes the state as it is, without an undo step
+    pub fn rebase(&mut self, state: T) {
+        self.current = Some(state);
+    }
+
+    pub fn undo(&mut self) -> Option<T> {
+        let state = self.undo.pop()?;
+        self.redo.extend(self.current.replace(state.clone()));
+        Some(state)
+    }
+
+    pub fn redo(&mut self) -> Option<T> {
+        let state = self.redo.pop()?;
+        self.undo.extend(self.current.replace(state.clone()));
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logistic_regression::*;
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = History::default();
+        history.rebase(0);
+        assert!(history.record(1));
+        assert!(history.record(2));
+        assert!(!history.record(2));
+
+        assert_eq!(history.undo(), Some(1));
+        assert_eq!(history.undo(), Some(0));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo(), Some(1));
+        assert_eq!(history.redo(), Some(2));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn recording_after_undo_drops_the_redo_branch() {
+        let mut history = History::default();
+        history.rebase(0);
+        history.record(1);
+        history.record(2);
+        history.undo();
+        assert!(history.record(3));
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(1));
+    }
+
+    #[test]
+    fn keeps_at_most_the_limit_of_steps() {
+        let mut history = History::default();
+        history.rebase(0);
+        for state in 1..=150 {
+            history.record(state);
+        }
+        let mut steps = Vec::new();
+        while let Some(state) = history.undo() {
+            steps.push(state);
+        }
+        assert_eq!(steps.len(), History::<i32>::LIMIT);
+        assert_eq!(steps.last(), Some(&50));
+    }
+
+    #[test]
+    fn nan_measurements_are_unchanged() {
+        let mut microplate = Microplate::new(12, 8);
+        microplate.samples[0].value = Some(f64::NAN);
+        let snapshot = Snapshot { project: Project::new(microplate), current_plate: 0 };
+
+        let mut history = History::default();
+        history.rebase(snapshot.clone());
+        assert!(!history.record(snapshot.clone()));
+        assert!(history.undo().is_none());
+
+        let mut edited = snapshot;
+        edited.project.plates[0].samples[1].value = Some(0.5);
+        assert!(history.record(edited));
+    }
+}
@@ -0,0 +1,259 @@
+use std::{fs, io};
+
+use calamine::{Data, DataType, Range};
+use chrono::{Duration, NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::{logistic_regression::*, templates::*};
+
+/// Block of cells, rows and columns count from 0 like in calamine
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CellRange {
+    pub start: (usize, usize), // row and column of the top-left cell
+    pub end: (usize, usize),   // of the bottom-right cell, inclusive
+}
+
+impl CellRange {
+    /// The block of a plate with its top-left well at the cell
+    pub fn from_corner(start: (usize, usize), width: usize, height: usize) -> Self {
+        Self { start, end: (start.0 + height.max(1) - 1, start.1 + width.max(1) - 1) }
+    }
+
+    pub fn width(&self) -> usize {
+        self.end.1 - self.start.1 + 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.end.0 - self.start.0 + 1
+    }
+
+    pub fn contains(&self, (row, column): (usize, usize)) -> bool {
+        (self.start.0..=self.end.0).contains(&row) && (self.start.1..=self.end.1).contains(&column)
+    }
+
+    /// An address like B38:M45, or a single cell
+    pub fn parse(address: &str) -> Option<Self> {
+        let mut cells = address.trim().split(':').map(parse_cell);
+        let start = cells.next()??;
+        let end = cells.next().unwrap_or(Some(start))?;
+        if cells.next().is_some() || end.0 < start.0 || end.1 < start.1 { return None }
+        Some(Self { start, end })
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", cell_address(self.start), cell_address(self.end))
+    }
+}
+
+/// Excel address of a cell, like B38
+pub fn cell_address((row, column): (usize, usize)) -> String {
+    format!("{}{}", column_name(column), row + 1)
+}
+
+/// Excel letters of a column, A to Z, then AA, AB and so on
+pub fn column_name(column: usize) -> String {
+    let mut letters = String::new();
+    let mut column = column + 1;
+    while column > 0 {
+        letters.insert(0, (b'A' + ((column - 1) % 26) as u8) as char);
+        column = (column - 1) / 26;
+    }
+    letters
+}
+
+fn parse_cell(cell: &str) -> Option<(usize, usize)> {
+    let cell = cell.trim().to_ascii_uppercase();
+    let split = cell.find(|char: char| char.is_ascii_digit())?;
+    let (letters, digits) = cell.split_at(split);
+    if letters.is_empty() || !letters.bytes().all(|byte| byte.is_ascii_uppercase()) { return None }
+    let column = letters.bytes().fold(0, |column, byte| column * 26 + (byte - b'A') as usize + 1);
+    let row = digits.parse::<usize>().ok()?;
+    (row > 0).then(|| (row - 1, column - 1))
+}
+
+/// A text export as a sheet, split into cells at tabs, or at semicolons or commas if there are no tabs.
+/// Numbers become numbers, also with a decimal comma.
+pub fn text_sheet(bytes: &[u8]) -> Range<Data> {
+    // Some readers write UTF-16 with a byte order mark
+    let text = match bytes {
+        [0xFF, 0xFE, rest @ ..] => String::from_utf16_lossy(&rest.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect::<Vec<_>>()),
+        [0xFE, 0xFF, rest @ ..] => String::from_utf16_lossy(&rest.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect::<Vec<_>>()),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    let separator = ['\t', ';', ','].into_iter().find(|&separator| text.contains(separator)).unwrap_or('\t');
+
+    let rows: Vec<Vec<&str>> = text.lines().map(|line| line.split(separator).collect()).collect();
+    let width = rows.iter().map(Vec::len).max().unwrap_or_default();
+    if rows.is_empty() || width == 0 { return Range::empty() }
+
+    let mut sheet = Range::new((0, 0), (rows.len() as u32 - 1, width as u32 - 1));
+    for (row, cells) in rows.iter().enumerate() {
+        for (column, cell) in cells.iter().enumerate() {
+            let cell = cell.trim().trim_matches('"');
+            let value = if cell.is_empty() {
+                Data::Empty
+            } else if let Ok(number) = cell.replace(',', ".").parse::<f64>() {
+                Data::Float(number)
+            } else {
+                Data::String(cell.to_owned())
+            };
+            sheet.set_value((row as u32, column as u32), value);
+        }
+    }
+    sheet
+}
+
+/// Trimmed text of a cell, numbers without decimals
+pub fn cell_text(sheet: &Range<Data>, (row, column): (usize, usize)) -> String {
+    match sheet.get_value((row as u32, column as u32)) {
+        Some(Data::DateTime(date_time)) => excel_date_time(date_time.as_f64()),
+        Some(Data::DateTimeIso(text)) => text.clone(),
+        value => value.and_then(DataType::as_string).map(|text| text.trim().to_owned()).unwrap_or_default(),
+    }
+}
+
+// Excel counts the days from 30.12.1899, the time is the fraction of a day
+fn excel_date_time(value: f64) -> String {
+    let days = value.trunc();
+    let seconds = ((value - days) * 86400.0).round().min(86399.0) as u32;
+    let time = NaiveTime::from_num_seconds_from_midnight_opt(seconds, 0).unwrap_or_default();
+    let Some(date) = NaiveDate::from_ymd_opt(1899, 12, 30).and_then(|start| start.checked_add_signed(Duration::days(days as i64))) else {
+        return value.to_string()
+    };
+    match (days == 0.0, seconds == 0) {
+        (true, _) => time.format("%H:%M:%S").to_string(),
+        (false, true) => date.format("%d.%m.%Y").to_string(),
+        (false, false) => format!("{}, {}", date.format("%d.%m.%Y"), time.format("%H:%M:%S")),
+    }
+}
+
+/// Finds the measurements of a plate by its headers, row letters A, B, ... down a column
+/// and column numbers 1, 2, ... along the row above. Exports often repeat the headers over
+/// a layout or several reads, so the block with the most numbers wins and ties go to the last.
+pub fn detect_plate(sheet: &Range<Data>) -> Option<CellRange> {
+    let (start, end) = (sheet.start()?, sheet.end()?);
+    let mut best: Option<(CellRange, usize)> = None;
+
+    for row in start.0 as usize + 1..=end.0 as usize {
+        for column in start.1 as usize..=end.1 as usize {
+            if cell_text(sheet, (row, column)) != "A" { continue }
+            let height = (0..).take_while(|&i| cell_text(sheet, (row + i, column)) == Microplate::row_name(i)).count();
+            let width = (0..).take_while(|&i| cell_text(sheet, (row - 1, column + 1 + i)) == (i + 1).to_string()).count();
+            if height < 2 || width < 2 { continue }
+
+            let range = CellRange::from_corner((row, column + 1), width, height);
+            let numbers = read_range(sheet, range).iter().flatten().filter(|value| value.is_some()).count();
+            if best.is_none_or(|(_, most)| numbers >= most) {
+                best = Some((range, numbers));
+            }
+        }
+    }
+    best.map(|(range, _)| range)
+}
+
+/// Numbers of the block row by row, empty and text cells are None
+pub fn read_range(sheet: &Range<Data>, range: CellRange) -> Vec<Vec<Option<f64>>> {
+    (range.start.0..=range.end.0).map(|row| {
+        (range.start.1..=range.end.1)
+            .map(|column| sheet.get_value((row as u32, column as u32)).and_then(DataType::as_f64))
+            .collect()
+    }).collect()
+}
+
+/// Where the measurements are in the exports of one reader, to import them again without picking
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportProfile {
+    pub name: String,
+    pub sheet: usize,
+    pub range: Option<CellRange>, // None to search for the headers
+}
+
+/// Saved profiles, none if the file is missing or cannot be read
+pub fn load_profiles() -> Vec<ImportProfile> {
+    config_directory()
+        .and_then(|directory| fs::read(directory.join("import_profiles.json")).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_profiles(profiles: &[ImportProfile]) -> io::Result<()> {
+    let directory = config_directory().ok_or(io::ErrorKind::NotFound)?;
+    fs::create_dir_all(&directory)?;
+    let serialized = serde_json::to_string(profiles).map_err(io::Error::other)?;
+    fs::write(directory.join("import_profiles.json"), serialized)
+}
+
+#[cfg(test)]
+mod tests {
+    use calamine::{ExcelDateTime, ExcelDateTimeType};
+
+    use super::*;
+
+    #[test]
+    fn parses_cell_ranges() {
+        let range = CellRange::parse("B38:M45").unwrap();
+        assert_eq!(range, CellRange { start: (37, 1), end: (44, 12) });
+        assert_eq!((range.width(), range.height()), (12, 8));
+        assert_eq!(range.address(), "B38:M45");
+        assert!(range.contains((40, 5)) && !range.contains((45, 5)));
+
+        assert_eq!(CellRange::parse(" aa1 "), Some(CellRange { start: (0, 26), end: (0, 26) }));
+        for invalid in ["", "M45:B38", "A0", "12", "B", "A1:B2:C3", "A-1"] {
+            assert_eq!(CellRange::parse(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn names_columns_like_excel() {
+        for (column, name) in [(0, "A"), (25, "Z"), (26, "AA"), (51, "AZ"), (701, "ZZ"), (702, "AAA")] {
+            assert_eq!(column_name(column), name);
+        }
+        assert_eq!(cell_address((0, 27)), "AB1");
+    }
+
+    #[test]
+    fn splits_text_exports() {
+        let sheet = text_sheet(b"Plate\tRead 1\n1\t0.5\n");
+        assert_eq!(cell_text(&sheet, (0, 1)), "Read 1");
+        assert_eq!(sheet.get_value((1, 1)), Some(&Data::Float(0.5)));
+
+        // Semicolons with decimal commas, as written with a German locale
+        let sheet = text_sheet(b"\"A\";0,25;1,5\nB;;2\n");
+        assert_eq!(cell_text(&sheet, (0, 0)), "A");
+        assert_eq!(read_range(&sheet, CellRange::parse("B1:C2").unwrap()), [[Some(0.25), Some(1.5)], [None, Some(2.0)]]);
+
+        let utf16: Vec<u8> = [0xFF, 0xFE].into_iter().chain("x\t1.5".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        let sheet = text_sheet(&utf16);
+        assert_eq!(cell_text(&sheet, (0, 0)), "x");
+        assert_eq!(sheet.get_value((0, 1)), Some(&Data::Float(1.5)));
+
+        assert!(text_sheet(b"").is_empty());
+    }
+
+    #[test]
+    fn formats_excel_dates() {
+        let mut sheet = Range::new((0, 0), (0, 2));
+        for (column, value) in [46312.0, 0.5, 46312.25].into_iter().enumerate() {
+            sheet.set_value((0, column as u32), Data::DateTime(ExcelDateTime::new(value, ExcelDateTimeType::DateTime, false)));
+        }
+        assert_eq!(cell_text(&sheet, (0, 0)), "17.10.2026");
+        assert_eq!(cell_text(&sheet, (0, 1)), "12:00:00");
+        assert_eq!(cell_text(&sheet, (0, 2)), "17.10.2026, 06:00:00");
+    }
+
+    // A layout with the same headers over the measurements, like many readers export it
+    #[test]
+    fn detects_the_plate_with_the_most_numbers() {
+        let export = "Layout\n\t1\t2\t3\nA\tSTD\tSTD\tSTD\nB\tSTD\tSTD\tSTD\n\nResults\n\t1\t2\t3\nA\t0.1\t0.2\t0.3\nB\t0.4\t0.5\t0.6\n";
+        let sheet = text_sheet(export.as_bytes());
+        let range = detect_plate(&sheet).unwrap();
+        assert_eq!(range.address(), "B8:D9");
+        assert_eq!(read_range(&sheet, range)[1], [Some(0.4), Some(0.5), Some(0.6)]);
+
+        // Ties go to the last block, a second read replaces the first
+        let export = "\t1\t2\nA\t1\t2\nB\t3\t4\n\t1\t2\nA\t5\t6\nB\t7\t8\n";
+        assert_eq!(detect_plate(&text_sheet(export.as_bytes())).unwrap().address(), "B5:C6");
+
+        assert_eq!(detect_plate(&text_sheet(b"A\t1\nB\t2\n")), None);
+    }
+}
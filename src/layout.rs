@@ -0,0 +1,219 @@
+use crate::{*, logistic_regression::*};
+
+/// Which way the replicates of a group lie next to each other
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Orientation {
+    #[default]
+    Vertical,   // groups run down the rows, replicates in neighbouring columns
+    Horizontal, // groups run along the columns, replicates in neighbouring rows
+}
+
+impl Orientation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Orientation::Vertical => "Vertical",
+            Orientation::Horizontal => "Horizontal",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Placement {
+    #[default]
+    None,
+    AfterStandards,
+    EndOfPlate,
+}
+
+impl Placement {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placement::None => "None",
+            Placement::AfterStandards => "After the standards",
+            Placement::EndOfPlate => "End of the plate",
+        }
+    }
+}
+
+/// Serial dilution of the standards and the replicate pattern of a whole plate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutWizard {
+    pub standards: usize,
+    pub replicates: usize,
+    pub orientation: Orientation,
+    pub top_concentration: f64,
+    pub dilution_factor: f64, // 2 for 1:2
+    pub blank: Placement,
+    pub control: Placement,
+    pub unknowns: bool, // fill the rest of the plate with unknowns
+}
+
+impl Default for LayoutWizard {
+    fn default() -> Self {
+        Self {
+            standards: 8,
+            replicates: 2,
+            orientation: Orientation::default(),
+            top_concentration: 1000.0,
+            dilution_factor: 2.0,
+            blank: Placement::AfterStandards,
+            control: Placement::None,
+            unknowns: true,
+        }
+    }
+}
+
+impl LayoutWizard {
+    /// Places for a group of replicates on the plate
+    pub fn slots(&self, microplate: &Microplate) -> usize {
+        let replicates = self.replicates.max(1);
+        match self.orientation {
+            Orientation::Vertical => microplate.width / replicates * microplate.height,
+            Orientation::Horizontal => microplate.height / replicates * microplate.width,
+        }
+    }
+
+    /// Slots taken by the standards, blank and control
+    pub fn slots_needed(&self) -> usize {
+        let placed = |placement: Placement| usize::from(placement != Placement::None);
+        self.standards + placed(self.blank) + placed(self.control)
+    }
+
+    // Wells of a slot, they fill a lane of neighbouring columns (or rows) before the next one
+    fn wells(&self, microplate: &Microplate, slot: usize) -> Vec<usize> {
+        let Microplate { width, height, .. } = *microplate;
+        (0..self.replicates).map(|replicate| match self.orientation {
+            Orientation::Vertical => {
+                let (lane, row) = (slot / height, slot % height);
+                (lane * self.replicates + replicate) * height + row
+            }
+            Orientation::Horizontal => {
+                let (lane, column) = (slot / width, slot % width);
+                column * height + lane * self.replicates + replicate
+            }
+        }).collect()
+    }
+
+    /// Lays out the plate from scratch, measurements stay. False if the layout does not fit on the plate.
+    pub fn apply(&self, microplate: &mut Microplate) -> bool {
+        let slots = self.slots(microplate);
+        if self.replicates == 0 || self.standards == 0 || self.slots_needed() > slots { return false }
+
+        let mut layout = vec![(SampleType::Unused, 0); slots];
+        let mut next = 0;
+        for i in 0..self.standards {
+            layout[next] = (SampleType::Standard, i);
+            next += 1;
+        }
+        let mut last = slots;
+        for (typ, placement) in [(SampleType::Blank, self.blank), (SampleType::Control, self.control)] {
+            match placement {
+                Placement::None => (),
+                Placement::AfterStandards => {
+                    layout[next] = (typ, 0);
+                    next += 1;
+                }
+                Placement::EndOfPlate => {
+                    last -= 1;
+                    layout[last] = (typ, 0);
+                }
+            }
+        }
+        if self.unknowns {
+            for (i, slot) in layout[next..last].iter_mut().enumerate() {
+                *slot = (SampleType::Unknown, i);
+            }
+        }
+
+        for sample in &mut microplate.samples {
+            sample.typ = SampleType::Unused;
+            sample.group = 0;
+            sample.dilution = None;
+        }
+        for (slot, &(typ, group)) in layout.iter().enumerate() {
+            for well in self.wells(microplate, slot) {
+                microplate.samples[well].typ = typ;
+                microplate.samples[well].group = group;
+            }
+        }
+
+        microplate.standard_groups = (0..self.standards).map(|i| Group {
+            concentration: Some(self.top_concentration / self.dilution_factor.powi(i as i32)),
+            ..default()
+        }).collect();
+        microplate.unknown_groups.clear();
+        microplate.resize_groups();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Type and group of a well by its name, like B3
+    fn well(microplate: &Microplate, name: &str) -> (SampleType, usize) {
+        let index = (0..microplate.samples.len()).find(|&i| microplate.well_name(i) == name).unwrap();
+        (microplate.samples[index].typ, microplate.samples[index].group)
+    }
+
+    #[test]
+    fn vertical_replicates_in_neighbouring_columns() {
+        let mut microplate = Microplate::new(12, 8);
+        assert!(LayoutWizard::default().apply(&mut microplate));
+        assert_eq!(well(&microplate, "A1"), (SampleType::Standard, 0));
+        assert_eq!(well(&microplate, "A2"), (SampleType::Standard, 0));
+        assert_eq!(well(&microplate, "H2"), (SampleType::Standard, 7));
+        // The blank takes the slot after the standards, the unknowns the rest
+        assert_eq!(well(&microplate, "A3"), (SampleType::Blank, 0));
+        assert_eq!(well(&microplate, "A4"), (SampleType::Blank, 0));
+        assert_eq!(well(&microplate, "B3"), (SampleType::Unknown, 0));
+        assert_eq!(well(&microplate, "H12"), (SampleType::Unknown, 38));
+        assert_eq!(microplate.unknown_groups.len(), 39);
+    }
+
+    #[test]
+    fn horizontal_replicates_in_neighbouring_rows() {
+        let mut microplate = Microplate::new(12, 8);
+        let wizard = LayoutWizard { orientation: Orientation::Horizontal, replicates: 3, unknowns: false, ..default() };
+        assert_eq!(wizard.slots(&microplate), 24);
+        assert!(wizard.apply(&mut microplate));
+        for (name, expected) in [("A1", (SampleType::Standard, 0)), ("C1", (SampleType::Standard, 0)), ("A2", (SampleType::Standard, 1)),
+                                 ("C8", (SampleType::Standard, 7)), ("A9", (SampleType::Blank, 0)), ("D1", (SampleType::Unused, 0)), ("H1", (SampleType::Unused, 0))] {
+            assert_eq!(well(&microplate, name), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn blank_and_control_at_the_end_of_the_plate() {
+        let mut microplate = Microplate::new(12, 8);
+        let wizard = LayoutWizard { blank: Placement::EndOfPlate, control: Placement::EndOfPlate, ..default() };
+        assert!(wizard.apply(&mut microplate));
+        assert_eq!(well(&microplate, "H11"), (SampleType::Blank, 0));
+        assert_eq!(well(&microplate, "H12"), (SampleType::Blank, 0));
+        assert_eq!(well(&microplate, "G12"), (SampleType::Control, 0));
+        assert_eq!(well(&microplate, "A3"), (SampleType::Unknown, 0));
+        assert_eq!(microplate.unknown_groups.len(), 48 - 10);
+    }
+
+    #[test]
+    fn serial_dilution_of_the_standards() {
+        let mut microplate = Microplate::new(12, 8);
+        let wizard = LayoutWizard { standards: 4, top_concentration: 900.0, dilution_factor: 3.0, ..default() };
+        assert!(wizard.apply(&mut microplate));
+        let concentrations: Vec<_> = microplate.standard_groups.iter().map(|group| group.concentration).collect();
+        assert_eq!(concentrations, [Some(900.0), Some(300.0), Some(100.0), Some(900.0 / 27.0)]);
+    }
+
+    #[test]
+    fn rejects_a_layout_that_does_not_fit() {
+        let mut microplate = Microplate::new(12, 8);
+        microplate.samples[0].value = Some(0.5);
+        let before = microplate.clone();
+        let wizard = LayoutWizard { standards: 32, replicates: 3, ..default() };
+        assert_eq!((wizard.slots_needed(), wizard.slots(&microplate)), (33, 32));
+        assert!(!wizard.apply(&mut microplate));
+        assert!(microplate.samples == before.samples && microplate.standard_groups == before.standard_groups);
+        assert!(!LayoutWizard { replicates: 0, ..default() }.apply(&mut microplate));
+    }
+}
@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Convergence {
+    #[default]
+    NotFitted,
+    Converged { iterations: usize },
+    MaxIterations,    // Ran out of iterations before the tolerance was reached
+    SingularJacobian, // Some parameter has no influence on the curve, the fit cannot be trusted
+    Stalled,          // No step reduced the error, but the gradient is not flat
+    Interpolated,     // The curve goes through the standards, there was nothing to fit
+}
+
+impl Convergence {
+    pub fn description(&self) -> String {
+        match self {
+            Convergence::NotFitted => String::from("Not fitted"),
+            Convergence::Converged { iterations } => format!("Converged after {iterations} iterations"),
+            Convergence::MaxIterations => String::from("Did not converge, iteration limit reached"),
+            Convergence::SingularJacobian => String::from("Did not converge, singular Jacobian"),
+            Convergence::Stalled => String::from("Did not converge, stalled away from a minimum"),
+            Convergence::Interpolated => String::from("Interpolated through the standards"),
+        }
+    }
+
+    pub fn is_converged(&self) -> bool {
+        matches!(self, Convergence::Converged { .. } | Convergence::Interpolated)
+    }
+}
+
+/// Damped least squares, interpolates between Gauss-Newton and gradient descent
+/// by scaling the diagonal of the normal equations with lambda.
+pub struct LevenbergMarquardt {
+    pub max_iterations: usize,
+    pub tolerance: f64,
+}
+
+impl Default for LevenbergMarquardt {
+    fn default() -> Self {
+        Self {
+            max_iterations: 1000,
+            tolerance: 1e-12,
+        }
+    }
+}
+
+impl LevenbergMarquardt {
+    /// Minimizes the weighted sum of squared residuals of `model` over `points`.
+    ///
+    /// `model` returns the curve value at x and writes its partial derivatives into the slice.
+    /// `constrain` is applied to every trial step, e.g. to keep parameters inside their bounds.
+    pub fn minimize(
+        &self,
+        parameters: &mut [f64],
+        points: &[(f64, f64)],
+        weights: &[f64],
+        model: impl Fn(f64, &[f64], &mut [f64]) -> f64,
+        constrain: impl Fn(&mut [f64]),
+    ) -> Convergence {
+        let n = parameters.len();
+        let mut gradient = vec![0.0; n];
+        let mut trial = parameters.to_vec();
+
+        let sum_of_squares = |parameters: &[f64], gradient: &mut [f64]| -> f64 {
+            points.iter().zip(weights).map(|(&(x, y), weight)| {
+                let diff = y - model(x, parameters, gradient);
+                weight * diff * diff
+            }).sum()
+        };
+
+        constrain(parameters);
+        let mut sse = sum_of_squares(parameters, &mut gradient);
+        let mut lambda = 1e-3;
+
+        for iteration in 1..=self.max_iterations {
+            // Normal equations J^T W J and J^T W r
+            let mut jtj = vec![vec![0.0; n]; n];
+            let mut jtr = vec![0.0; n];
+            for (&(x, y), weight) in points.iter().zip(weights) {
+                let diff = y - model(x, parameters, &mut gradient);
+                for (i, (row, jtr)) in jtj.iter_mut().zip(jtr.iter_mut()).enumerate() {
+                    *jtr += weight * gradient[i] * diff;
+                    for (cell, gradient_j) in row.iter_mut().zip(&gradient) {
+                        *cell += weight * gradient[i] * gradient_j;
+                    }
+                }
+            }
+
+            if jtj.iter().enumerate().any(|(i, row)| row[i].is_nan() || row[i] <= f64::EPSILON * f64::EPSILON) {
+                return Convergence::SingularJacobian
+            }
+
+            // Increase damping until the step reduces the error
+            loop {
+                let mut damped = jtj.clone();
+                for (i, row) in damped.iter_mut().enumerate() {
+                    row[i] *= 1.0 + lambda;
+                }
+
+                let Some(step) = solve(damped, jtr.clone()) else {
+                    return Convergence::SingularJacobian
+                };
+
+                for ((t, p), s) in trial.iter_mut().zip(parameters.iter()).zip(&step) {
+                    *t = p + s;
+                }
+                constrain(&mut trial);
+                let trial_sse = sum_of_squares(&trial, &mut gradient);
+
+                if trial_sse.is_finite() && trial_sse <= sse {
+                    let step_size = trial.iter().zip(parameters.iter())
+                        .map(|(t, p)| ((t - p) / p.abs().max(1e-8)).abs())
+                        .fold(0.0, f64::max);
+                    let reduction = (sse - trial_sse) / sse.max(f64::MIN_POSITIVE);
+
+                    parameters.copy_from_slice(&trial);
+                    sse = trial_sse;
+                    lambda = (lambda / 10.0).max(1e-12);
+
+                    if reduction < self.tolerance || step_size < self.tolerance.sqrt() {
+                        return Convergence::Converged { iterations: iteration }
+                    }
+                    break
+                }
+
+                lambda *= 10.0;
+                // No step reduces the error any more. That is a minimum only if the gradient is flat,
+                // measured as the cosine between the residuals and every column of the Jacobian.
+                if lambda > 1e16 {
+                    let flat = sse <= f64::MIN_POSITIVE || jtr.iter().enumerate()
+                        .all(|(i, g)| g.abs() <= self.tolerance.sqrt() * (jtj[i][i] * sse).sqrt());
+                    return if flat { Convergence::Converged { iterations: iteration } } else { Convergence::Stalled }
+                }
+            }
+        }
+
+        Convergence::MaxIterations
+    }
+}
+
+/// Gaussian elimination with partial pivoting, returns None if the matrix is singular
+fn solve(mut matrix: Vec<Vec<f64>>, mut vector: Vec<f64>) -> Option<Vec<f64>> {
+    let n = vector.len();
+    let scale = matrix.iter().flatten().fold(0.0, |max: f64, v| max.max(v.abs()));
+
+    for column in 0..n {
+        let pivot = (column..n).max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        let pivot_value = matrix[pivot][column].abs();
+        if pivot_value.is_nan() || pivot_value <= scale * 1e-15 { return None }
+        matrix.swap(column, pivot);
+        vector.swap(column, pivot);
+
+        let (upper, lower) = matrix.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (cell, pivot_cell) in row.iter_mut().zip(pivot_row).skip(column) {
+                *cell -= factor * pivot_cell;
+            }
+            vector[column + 1 + offset] -= factor * vector[column];
+        }
+    }
+
+    let mut result = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|i| matrix[row][i] * result[i]).sum();
+        result[row] = (vector[row] - sum) / matrix[row][row];
+    }
+    Some(result)
+}
+
+/// J^T W J at the given parameters, its inverse scaled by the residual variance is the covariance of the parameters
+pub fn information_matrix(
+    parameters: &[f64],
+    points: &[(f64, f64)],
+    weights: &[f64],
+    model: impl Fn(f64, &[f64], &mut [f64]) -> f64,
+) -> Vec<Vec<f64>> {
+    let n = parameters.len();
+    let mut gradient = vec![0.0; n];
+    let mut jtj = vec![vec![0.0; n]; n];
+    for (&(x, _y), weight) in points.iter().zip(weights) {
+        model(x, parameters, &mut gradient);
+        for (i, row) in jtj.iter_mut().enumerate() {
+            for (cell, gradient_j) in row.iter_mut().zip(&gradient) {
+                *cell += weight * gradient[i] * gradient_j;
+            }
+        }
+    }
+    jtj
+}
+
+/// Matrix inverse, column by column, returns None if the matrix is singular
+pub fn invert(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut inverse = vec![vec![0.0; n]; n];
+    for column in 0..n {
+        let mut unit = vec![0.0; n];
+        unit[column] = 1.0;
+        let solution = solve(matrix.to_vec(), unit)?;
+        for (row, value) in inverse.iter_mut().zip(solution) {
+            row[column] = value;
+        }
+    }
+    Some(inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logistic_regression::log_logistic;
+
+    #[test]
+    fn recovers_four_pl_parameters() {
+        // a, b, ln(c), d of a curve rising from 0.1 to 2.5 around 150
+        let expected = [0.1, 1.3, 150f64.ln(), 2.5];
+        let points: Vec<_> = [1000.0, 500.0, 250.0, 125.0, 62.5, 31.25, 15.6, 7.8].iter().map(|&x: &f64| {
+            let x = x.ln();
+            (x, log_logistic(x, expected[0], expected[1], expected[2], expected[3], 1.0, &mut [0.0; 4]))
+        }).collect();
+        let weights = vec![1.0; points.len()];
+
+        let mut parameters = vec![0.0, 1.0, 100f64.ln(), 3.0];
+        let convergence = LevenbergMarquardt::default().minimize(
+            &mut parameters,
+            &points,
+            &weights,
+            |x, p, gradient| log_logistic(x, p[0], p[1], p[2], p[3], 1.0, gradient),
+            |_p| (),
+        );
+
+        assert!(convergence.is_converged(), "{convergence:?}");
+        for (parameter, expected) in parameters.iter().zip(expected) {
+            assert!((parameter - expected).abs() < 1e-6, "{parameters:?}");
+        }
+    }
+
+    #[test]
+    fn fits_a_weighted_line() {
+        let points = [(0.0, 1.0), (1.0, 3.0), (2.0, 5.0), (3.0, 7.0)];
+        let weights = [1.0, 0.5, 2.0, 1.0];
+        let mut parameters = vec![0.0, 0.0];
+        let convergence = LevenbergMarquardt::default().minimize(
+            &mut parameters,
+            &points,
+            &weights,
+            |x, p, gradient| {
+                gradient[0] = 1.0;
+                gradient[1] = x;
+                p[0] + p[1] * x
+            },
+            |_p| (),
+        );
+        assert!(convergence.is_converged());
+        assert!((parameters[0] - 1.0).abs() < 1e-8 && (parameters[1] - 2.0).abs() < 1e-8, "{parameters:?}");
+    }
+
+    #[test]
+    fn parameter_without_influence_is_singular() {
+        let points = [(0.0, 1.0), (1.0, 2.0), (2.0, 3.0)];
+        let mut parameters = vec![0.0, 0.0];
+        let convergence = LevenbergMarquardt::default().minimize(
+            &mut parameters,
+            &points,
+            &[1.0; 3],
+            |x, p, gradient| {
+                gradient[0] = x;
+                gradient[1] = 0.0;
+                p[0] * x
+            },
+            |_p| (),
+        );
+        assert_eq!(convergence, Convergence::SingularJacobian);
+    }
+
+    #[test]
+    fn inverts_a_matrix() {
+        let inverse = invert(&[vec![4.0, 7.0], vec![2.0, 6.0]]).unwrap();
+        let expected = [[0.6, -0.7], [-0.2, 0.4]];
+        for (row, expected) in inverse.iter().zip(expected) {
+            for (cell, expected) in row.iter().zip(expected) {
+                assert!((cell - expected).abs() < 1e-12, "{inverse:?}");
+            }
+        }
+        assert!(invert(&[vec![1.0, 2.0], vec![2.0, 4.0]]).is_none());
+    }
+}
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+
+use crate::logistic_regression::*;
+
+/// Which wells the limit of detection is derived from, LOD = mean + k SD
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DetectionMethod {
+    BlankSd { k: f64 },   // Blank wells
+    ControlSd { k: f64 }, // Control wells, the 0% standard
+}
+
+impl Default for DetectionMethod {
+    fn default() -> Self {
+        DetectionMethod::BlankSd { k: 3.0 }
+    }
+}
+
+impl DetectionMethod {
+    pub fn name(&self) -> String {
+        match self {
+            DetectionMethod::BlankSd { k } => format!("Mean blank + {k} SD"),
+            DetectionMethod::ControlSd { k } => format!("Mean control + {k} SD"),
+        }
+    }
+}
+
+/// How the limits of quantification are chosen
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum QuantificationMethod {
+    StandardRange,                        // lowest and highest standard
+    Precision { recovery: f64, cv: f64 }, // outermost standards with recovery within 100 +- recovery % and %CV below cv
+    BlankSd { k: f64 },                   // LLOQ at mean blank + k SD, ULOQ at the highest standard
+}
+
+impl Default for QuantificationMethod {
+    fn default() -> Self {
+        QuantificationMethod::Precision { recovery: 20.0, cv: 20.0 }
+    }
+}
+
+impl QuantificationMethod {
+    pub fn name(&self) -> String {
+        match self {
+            QuantificationMethod::StandardRange => String::from("Standard range"),
+            QuantificationMethod::Precision { recovery, cv } => format!("Recovery 100 +- {recovery}%, CV < {cv}%"),
+            QuantificationMethod::BlankSd { k } => format!("Mean blank + {k} SD"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LimitOptions {
+    #[serde(default)]
+    pub detection: DetectionMethod,
+    #[serde(default)]
+    pub quantification: QuantificationMethod,
+}
+
+/// Concentrations, NaN if they could not be determined
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub lod: f64,
+    pub lloq: f64,
+    pub uloq: f64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            lod: f64::NAN,
+            lloq: f64::NAN,
+            uloq: f64::NAN,
+        }
+    }
+}
+
+/// Why an unknown has no reportable concentration
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RangeFlag {
+    OutOfAsymptotes, // The response lies beyond the curve, there is no backfit
+    BelowLloq,
+    AboveUloq,
+    NoWorkingRange,  // The limits of quantification could not be determined
+}
+
+impl RangeFlag {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RangeFlag::OutOfAsymptotes => "out of asymptotes",
+            RangeFlag::BelowLloq => "<LLOQ",
+            RangeFlag::AboveUloq => ">ULOQ",
+            RangeFlag::NoWorkingRange => "no LOQ",
+        }
+    }
+}
+
+impl Regression {
+    pub fn calculate_limits(&mut self) {
+        let LimitOptions { detection, quantification } = self.options.limits;
+
+        // Responses are blank corrected, so the blank sits at 0. A decreasing curve starts at the top,
+        // there the noise of the blank is measured down from the 0-dose asymptote instead.
+        let top = if self.model.is_logistic() {
+            self.abcd.0
+        } else {
+            self.standards.iter().map(|&(_x, y)| y).fold(f64::MIN, f64::max)
+        };
+        let (zero_dose, sign) = match self.direction {
+            Direction::Decreasing => (top, -1.0),
+            _ => (0.0, 1.0),
+        };
+        // Without replicates there is no SD, and no limit
+        let limit = |mean: f64, k: f64, sd: f64| if sd > 0.0 { self.inverse(mean + sign * k * sd) } else { f64::NAN };
+        let lod = match detection {
+            DetectionMethod::BlankSd { k } => limit(zero_dose, k, self.blank_sd),
+            DetectionMethod::ControlSd { k } => limit(self.control, k, self.control_sd),
+        };
+
+        let highest_standard = self.standards.last().map_or(f64::NAN, |&(x, _y)| x);
+        let (lloq, uloq) = match quantification {
+            QuantificationMethod::StandardRange => {
+                (self.standards.first().map_or(f64::NAN, |&(x, _y)| x), highest_standard)
+            }
+            QuantificationMethod::Precision { recovery, cv } => {
+                let accepted: Vec<_> = self.standard_precision().into_iter()
+                    .filter(|&(_x, standard_recovery, standard_cv)| {
+                        (standard_recovery - 100.0).abs() <= recovery && standard_cv < cv
+                    })
+                    .map(|(x, _, _)| x)
+                    .collect();
+                (accepted.first().copied().unwrap_or(f64::NAN), accepted.last().copied().unwrap_or(f64::NAN))
+            }
+            QuantificationMethod::BlankSd { k } => (limit(zero_dose, k, self.blank_sd), highest_standard),
+        };
+        // Nothing below the detection limit can be quantified
+        let lloq = if lod > lloq { lod } else { lloq };
+
+        self.limits = Limits { lod, lloq, uloq };
+
+        for unknown in &mut self.unknowns {
+            unknown.flag = if !unknown.backfit.is_finite() || unknown.backfit <= 0.0 {
+                Some(RangeFlag::OutOfAsymptotes)
+            } else if lloq.is_nan() || uloq.is_nan() {
+                Some(RangeFlag::NoWorkingRange)
+            } else if unknown.backfit < lloq {
+                Some(RangeFlag::BelowLloq)
+            } else if unknown.backfit > uloq {
+                Some(RangeFlag::AboveUloq)
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Concentration, recovery % of the mean and %CV of the backfit replicates of every standard
+    pub fn standard_precision(&self) -> Vec<(f64, f64, f64)> {
+        self.replicates.chunk_by(|(a_x, _), (b_x, _)| a_x == b_x).map(|group| {
+            let x = group[0].0;
+            let backfits: Vec<_> = group.iter().map(|&(_x, y)| self.inverse(y)).collect();
+            let (mean, sd) = mean_and_sd(&backfits);
+            (x, mean / x * 100.0, sd / mean * 100.0)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default;
+
+    // A straight line, the standards replicates given by their backfit. The lowest standard scatters
+    // and the highest one is off, so the limits of quantification lie at 10 and 40.
+    fn regression(direction: Direction, coefficients: [f64; 2]) -> Regression {
+        let response = |x: f64| coefficients[0] + coefficients[1] * x;
+        let backfits = [(2.0, [1.0, 3.0]), (10.0, [10.0, 10.5]), (20.0, [19.0, 20.0]), (40.0, [40.0, 41.0]), (80.0, [120.0, 120.0])];
+        let mut regression = Regression {
+            model: Model::Linear,
+            direction,
+            coefficients: coefficients.to_vec(),
+            blank_sd: 1.0,
+            ..default()
+        };
+        for (x, replicates) in backfits {
+            regression.replicates.extend(replicates.map(|backfit| (x, response(backfit))));
+            regression.standards.push((x, response((replicates[0] + replicates[1]) / 2.0)));
+        }
+        regression.unknowns = [0.5, 5.0, 20.0, 50.0, -1.0].iter()
+            .map(|&backfit| Unknown { backfit, ..default() })
+            .collect();
+        regression
+    }
+
+    fn flags(regression: &Regression) -> Vec<Option<RangeFlag>> {
+        regression.unknowns.iter().map(|unknown| unknown.flag).collect()
+    }
+
+    #[test]
+    fn limits_of_an_increasing_curve() {
+        let mut regression = regression(Direction::Increasing, [0.0, 1.0]);
+        regression.calculate_limits();
+        let Limits { lod, lloq, uloq } = regression.limits;
+        // The blank is at 0 after blank correction
+        assert!((lod - 3.0).abs() < 1e-12 && lloq == 10.0 && uloq == 40.0, "{:?}", regression.limits);
+        // Below the LOD is below the LLOQ as well
+        assert_eq!(flags(&regression), [
+            Some(RangeFlag::BelowLloq), Some(RangeFlag::BelowLloq), None, Some(RangeFlag::AboveUloq), Some(RangeFlag::OutOfAsymptotes),
+        ]);
+
+        // A noisy blank moves the LLOQ up to the LOD
+        regression.blank_sd = 5.0;
+        regression.calculate_limits();
+        assert!((regression.limits.lloq - 15.0).abs() < 1e-12, "{:?}", regression.limits);
+        assert_eq!(regression.unknowns[2].flag, None);
+    }
+
+    #[test]
+    fn limits_of_a_decreasing_curve() {
+        let mut regression = regression(Direction::Decreasing, [100.0, -1.0]);
+        regression.calculate_limits();
+        let Limits { lod, lloq, uloq } = regression.limits;
+        // Measured down from the highest standard response, 98 at 2
+        assert!((lod - 5.0).abs() < 1e-12 && lloq == 10.0 && uloq == 40.0, "{:?}", regression.limits);
+        assert_eq!(flags(&regression), [
+            Some(RangeFlag::BelowLloq), Some(RangeFlag::BelowLloq), None, Some(RangeFlag::AboveUloq), Some(RangeFlag::OutOfAsymptotes),
+        ]);
+    }
+
+    #[test]
+    fn no_working_range_without_precise_standards() {
+        let mut regression = regression(Direction::Increasing, [0.0, 1.0]);
+        regression.options.limits.quantification = QuantificationMethod::Precision { recovery: 1.0, cv: 1.0 };
+        regression.blank_sd = 0.0;
+        regression.calculate_limits();
+        assert!(regression.limits.lod.is_nan() && regression.limits.lloq.is_nan());
+        assert_eq!(regression.unknowns[2].flag, Some(RangeFlag::NoWorkingRange));
+    }
+}
@@ -1,6 +1,7 @@
-use crate::*;
+use crate::{*, acceptance::*, dilution::*, levenberg_marquardt::*, limits::*, model_comparison::*, multi_start::*, potency::*, readers::*, statistics::*, units::*};
 use egui::Color32;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use SampleType::*;
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
@@ -11,6 +12,7 @@ pub enum SampleType {
     Control,  // Concentration of 0%
     Standard, // Standard values for curve
     Unknown,  // Unknowns we want to estimate
+    QualityControl, // Kit controls with a known concentration, backfitted like unknowns
 }
 
 impl SampleType {
@@ -21,24 +23,310 @@ impl SampleType {
             Standard => Color32::from_hex("#F57373").unwrap(),
             Control => Color32::from_hex("#818FEF").unwrap(),
             Blank => Color32::from_hex("#F1E07D").unwrap(),
+            QualityControl => Color32::from_hex("#E59CF0").unwrap(),
         }
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Sample {
     pub typ: SampleType,
     pub group: usize,        // index to group in microplate
     pub value: Option<f64>,
+    #[serde(default)]
+    pub excluded: Option<String>, // reason the well is left out of the fit
+    #[serde(default)]
+    pub dilution: Option<f64>,    // of an unknown well, if it differs from its group
 }
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Group {
     pub concentration: Option<f64>,
     pub label: String,
+    #[serde(default)]
+    pub dilution: Option<f64>, // of the unknowns, None if undiluted
+    #[serde(default)]
+    pub unit: Option<Unit>,    // of a standard, None if it is the unit of the microplate
+    #[serde(default)]
+    pub tolerance: Option<f64>, // % around the concentration of a quality control, None for the tolerance of the rule
 }
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Model {
+    #[default]
+    FourPL,       // Symmetric sigmoid
+    FivePL,       // Sigmoid with asymmetry parameter g
+    Linear,       // Straight line
+    LogLog,       // Straight line through log(x) and log(y)
+    Quadratic,    // Second order polynomial
+    CubicSpline,  // Natural cubic spline through the standards
+    PointToPoint, // Straight lines between the standards
+}
+
+impl Model {
+    pub const ALL: [Model; 7] = [
+        Model::FourPL, Model::FivePL, Model::Linear, Model::LogLog,
+        Model::Quadratic, Model::CubicSpline, Model::PointToPoint,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::FourPL => "4PL",
+            Model::FivePL => "5PL",
+            Model::Linear => "Linear",
+            Model::LogLog => "Log-log",
+            Model::Quadratic => "Quadratic",
+            Model::CubicSpline => "Cubic spline",
+            Model::PointToPoint => "Point-to-point",
+        }
+    }
+
+    /// Number of parameters, none for the interpolating models
+    pub fn parameter_count(&self) -> usize {
+        match self {
+            Model::FourPL => 4,
+            Model::FivePL => 5,
+            Model::Linear => 2,
+            Model::LogLog => 2,
+            Model::Quadratic => 3,
+            Model::CubicSpline | Model::PointToPoint => 0,
+        }
+    }
+
+    /// Standard concentrations needed to draw the curve, one per parameter, or enough to interpolate between
+    pub fn minimum_standards(&self) -> usize {
+        match self {
+            Model::CubicSpline => 3,
+            Model::PointToPoint => 2,
+            _ => self.parameter_count(),
+        }
+    }
+
+    pub fn is_logistic(&self) -> bool {
+        matches!(self, Model::FourPL | Model::FivePL)
+    }
+
+    /// Curves that pass through every standard, they have no parameters of their own
+    pub fn interpolates(&self) -> bool {
+        matches!(self, Model::CubicSpline | Model::PointToPoint)
+    }
+
+    /// The curve at concentration x over the solver parameters, writes the partial derivatives into `gradient`.
+    /// The interpolating models have no parameters and return NaN.
+    pub fn curve(&self, x: f64, p: &[f64], gradient: &mut [f64]) -> f64 {
+        match self {
+            Model::FourPL | Model::FivePL => {
+                log_logistic(x.ln(), p[0], p[1], p[2], p[3], p.get(4).copied().unwrap_or(1.0), gradient)
+            }
+            Model::Linear => {
+                gradient[0] = 1.0;
+                gradient[1] = x;
+                p[0] + p[1] * x
+            }
+            Model::LogLog => {
+                let y = 10f64.powf(p[0] + p[1] * x.log10());
+                gradient[0] = y * 10f64.ln();
+                gradient[1] = y * 10f64.ln() * x.log10();
+                y
+            }
+            Model::Quadratic => {
+                gradient[0] = 1.0;
+                gradient[1] = x;
+                gradient[2] = x * x;
+                p[0] + p[1] * x + p[2] * x * x
+            }
+            Model::CubicSpline | Model::PointToPoint => f64::NAN,
+        }
+    }
+
+    /// The curve in the space it is fitted in, log10 of the response for log-log
+    pub fn fitted_curve(&self, x: f64, p: &[f64], gradient: &mut [f64]) -> f64 {
+        match self {
+            Model::LogLog => Model::Linear.curve(x.log10(), p, gradient),
+            model => model.curve(x, p, gradient),
+        }
+    }
+}
+
+/// Whether the response rises or falls with the dose
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    #[default]
+    Auto,       // Detected from the standards
+    Increasing, // e.g. sandwich ELISA
+    Decreasing, // e.g. competitive ELISA
+}
+
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Auto => "Auto",
+            Direction::Increasing => "Increasing",
+            Direction::Decreasing => "Decreasing",
+        }
+    }
+
+    /// Sign of the least squares slope of the measurements against ln(concentration)
+    pub fn detect(standards: &[(f64, f64)]) -> Direction {
+        let n = standards.len() as f64;
+        let mean_x = standards.iter().map(|(x, _y)| x.ln()).sum::<f64>() / n;
+        let mean_y = standards.iter().map(|(_x, y)| y).sum::<f64>() / n;
+        let covariance: f64 = standards.iter().map(|(x, y)| (x.ln() - mean_x) * (y - mean_y)).sum();
+        if covariance < 0.0 { Direction::Decreasing } else { Direction::Increasing }
+    }
+}
+
+/// Variance model of the measurements, each standard is weighted by the inverse of its variance
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Weighting {
+    #[default]
+    None,
+    InverseY,        // 1/y
+    InverseYSquared, // 1/y^2
+    InverseXSquared, // 1/x^2
+    Power(f64),      // 1/y^theta with a custom theta
+    EstimatedPower,  // 1/y^theta with theta estimated from the replicate scatter of the standards
+}
+
+impl Weighting {
+    pub fn name(&self) -> String {
+        match self {
+            Weighting::None => String::from("None"),
+            Weighting::InverseY => String::from("1/Y"),
+            Weighting::InverseYSquared => String::from("1/Y^2"),
+            Weighting::InverseXSquared => String::from("1/X^2"),
+            Weighting::Power(theta) => format!("1/Y^{theta}"),
+            Weighting::EstimatedPower => String::from("Power of mean"),
+        }
+    }
+
+    /// Weights for the (x, y) points, normalized to a mean of 1 so the statistics stay in measurement units.
+    /// `theta` is only used for the estimated power of mean.
+    pub fn weights(&self, points: &[(f64, f64)], theta: f64) -> Vec<f64> {
+        let theta = match self {
+            Weighting::None => 0.0,
+            Weighting::InverseY => 1.0,
+            Weighting::InverseYSquared => 2.0,
+            Weighting::InverseXSquared => 0.0,
+            Weighting::Power(theta) => *theta,
+            Weighting::EstimatedPower => theta,
+        };
+
+        // Keep measurements close to zero from getting all the weight
+        let floor = points.iter().map(|(_x, y)| y.abs()).fold(0.0, f64::max) * 1e-3;
+        let weights: Vec<_> = points.iter().map(|&(x, y)| {
+            let weight = match self {
+                Weighting::InverseXSquared => 1.0 / x.abs().max(f64::MIN_POSITIVE).powi(2),
+                _ => 1.0 / y.abs().max(floor).max(f64::MIN_POSITIVE).powf(theta),
+            };
+            if weight.is_finite() { weight } else { 1.0 }
+        }).collect();
+
+        let mean = weights.iter().sum::<f64>() / weights.len() as f64;
+        weights.into_iter().map(|weight| weight / mean).collect()
+    }
+}
+
+/// Estimates theta in variance = sigma^2 * mean^theta from the replicates of each group,
+/// the slope of ln(variance) against ln(mean). Returns None with fewer than two usable groups.
+pub fn estimate_variance_power(groups: &[Vec<f64>]) -> Option<f64> {
+    let points: Vec<_> = groups.iter().filter(|values| values.len() >= 2).filter_map(|values| {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1.0);
+        (mean > 0.0 && variance > 0.0).then(|| (mean.ln(), variance.ln()))
+    }).collect();
+
+    if points.len() < 2 { return None }
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x) * (x - mean_x)).sum();
+    if variance <= 0.0 { return None }
+
+    // Anything outside of constant variance (0) to constant CV squared (4) is noise
+    Some((covariance / variance).clamp(0.0, 4.0))
+}
+
+/// How the confidence intervals of the parameters are calculated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum IntervalMethod {
+    #[default]
+    Asymptotic, // from the covariance matrix at the optimum
+    Bootstrap { resamples: usize, seed: u64 }, // refit on resampled residuals
+}
+
+impl IntervalMethod {
+    pub fn name(&self) -> String {
+        match self {
+            IntervalMethod::Asymptotic => String::from("Asymptotic"),
+            IntervalMethod::Bootstrap { resamples, seed } => format!("Bootstrap ({resamples} resamples, seed {seed})"),
+        }
+    }
+}
+
+/// Settings that change how the curve is fitted and read, but not the model itself
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FitOptions {
+    #[serde(default)]
+    pub multi_start: MultiStart,
+    #[serde(default)]
+    pub weighting: Weighting,
+    #[serde(default)]
+    pub fit_replicates: bool, // fit every standard well instead of the group means
+    #[serde(default)]
+    pub intervals: IntervalMethod,
+    #[serde(default)]
+    pub limits: LimitOptions,
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default)]
+    pub parallelism: ParallelismTest,
+}
+
+/// Standard errors and 95% confidence intervals of the parameters
+pub type ParameterIntervals = (Vec<f64>, Vec<(f64, f64)>);
+
+#[derive(Clone, Debug, Default)]
+pub struct Unknown {
+    pub label: String,
+    pub raw: f64,             // mean of the corrected replicates
+    pub backfit: f64,         // backfit of the mean
+    pub replicates: Vec<f64>, // corrected measurement of every well
+    pub backfits: Vec<f64>,   // backfit of every well
+    pub mean: f64,            // mean, standard deviation and %CV of the backfit wells
+    pub sd: f64,
+    pub cv: f64,
+    pub confidence_interval: (f64, f64), // 95% of the backfit in the well, from the curve and the scatter of the replicates
+    pub prediction_interval: (f64, f64), // 95% of the backfit in the well, from the curve and the residual scatter of the fit
+    pub flag: Option<RangeFlag>,         // None if the backfit lies within the limits of quantification
+    pub dilution: f64,                   // of the group, 1 if undiluted
+    pub dilutions: Vec<f64>,             // of every well
+    pub concentration: f64,              // in the original sample, the backfit times the dilution
+}
+
+impl Unknown {
+    /// Confidence and prediction interval in the original sample, scaled like the final concentration
+    pub fn final_intervals(&self) -> ((f64, f64), (f64, f64)) {
+        let scale = self.concentration / self.backfit;
+        let ((ci_low, ci_high), (pi_low, pi_high)) = (self.confidence_interval, self.prediction_interval);
+        ((ci_low * scale, ci_high * scale), (pi_low * scale, pi_high * scale))
+    }
+}
+
+/// Replicates of a quality control level, their nominal concentration is known
+#[derive(Clone, Debug, Default)]
+pub struct QualityControlSample {
+    pub label: String,
+    pub nominal: f64,
+    pub tolerance: Option<f64>,
+    pub replicates: Vec<f64>, // corrected measurement of every well
+    pub backfits: Vec<f64>,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Microplate {
     pub name: String,
     pub description: String,
@@ -47,9 +335,28 @@ pub struct Microplate {
     pub samples: Vec<Sample>,
     pub standard_groups: Vec<Group>,
     pub unknown_groups: Vec<Group>,
+    #[serde(default)]
+    pub quality_control_groups: Vec<Group>,
+    #[serde(default)]
+    pub model: Model,
+    #[serde(default)]
+    pub fit_options: FitOptions,
+    #[serde(default)]
+    pub unit: Unit, // of the fit and every result
+    #[serde(default)]
+    pub molecular_weight: Option<f64>, // g/mol, to convert between mass and molar standards
+    #[serde(default)]
+    pub acceptance: AcceptanceCriteria,
+    #[serde(default)]
+    pub verdict: Option<Verdict>, // of the last calculation
+    #[serde(default)]
+    pub read_info: ReadInfo, // of the export the measurements were imported from
 }
 
 impl Microplate {
+    /// Width and height of the plate formats from 6 to 1536 wells
+    pub const FORMATS: [(usize, usize); 7] = [(3, 2), (4, 3), (6, 4), (8, 6), (12, 8), (24, 16), (48, 32)];
+
     pub fn new(width: usize, height: usize) -> Self {
         Self {
             height,
@@ -57,9 +364,53 @@ impl Microplate {
             samples: vec![default(); width * height],
             standard_groups: vec![default()],
             unknown_groups: vec![default()],
+            quality_control_groups: vec![default()],
             ..default()
         }
     }
+
+    /// Row letters and column number, like B7 or AF48
+    pub fn well_name(&self, index: usize) -> String {
+        let (row, column) = (index % self.height, index / self.height);
+        format!("{}{}", Microplate::row_name(row), column + 1)
+    }
+
+    /// A to Z, then AA, AB and so on
+    pub fn row_name(row: usize) -> String {
+        let letter = |i: usize| (b'A' + i as u8) as char;
+        if row < 26 {
+            letter(row).to_string()
+        } else {
+            format!("{}{}", letter(row / 26 - 1), letter(row % 26))
+        }
+    }
+
+    /// Group of a standard, unknown or quality control well
+    pub fn group_mut(&mut self, index: usize) -> Option<&mut Group> {
+        let Sample { typ, group, .. } = self.samples[index];
+        match typ {
+            Standard => self.standard_groups.get_mut(group),
+            Unknown => self.unknown_groups.get_mut(group),
+            QualityControl => self.quality_control_groups.get_mut(group),
+            Unused | Blank | Control => None,
+        }
+    }
+
+    /// Keeps one group for every group number in use, and at least one of each
+    pub fn resize_groups(&mut self) {
+        let max_group = |typ: SampleType| self.samples.iter()
+            .filter(|sample| sample.typ == typ)
+            .map(|sample| sample.group)
+            .max().unwrap_or_default();
+        let (standards, unknowns, quality_controls) = (max_group(Standard), max_group(Unknown), max_group(QualityControl));
+        self.standard_groups.resize_with(standards + 1, default);
+        self.unknown_groups.resize_with(unknowns + 1, default);
+        self.quality_control_groups.resize_with(quality_controls + 1, default);
+    }
+
+    pub fn format_name(&self) -> String {
+        format!("{} wells", self.width * self.height)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -70,21 +421,49 @@ pub enum ValueError {
     InvalidValue,
     NotEnoughStandards,
     BlankTooBig,
+    ControlTooSmall, // only for decreasing curves, where the control is the top of the curve
+    IncompatibleUnits,
     ControlTooBig,
 }
 
 #[derive(Clone, Default)]
 pub struct Regression {
+    pub model: Model,
+    pub direction: Direction, // never Auto
+    pub options: FitOptions,
     pub abcd: (f64, f64, f64, f64),
+    pub g: f64, // asymmetry, always 1.0 for 4PL
+    pub coefficients: Vec<f64>, // of the polynomial models, or the second derivatives of the spline
+    pub knots: Vec<(f64, f64)>, // ln(concentration) and response the interpolating models pass through
     pub blank: f64,
+    pub blank_sd: f64,
     pub control: f64,
-    pub unknowns: Vec<(f64, f64, String)>,
-    pub standards: Vec<(f64, f64)>,
+    pub control_sd: f64,
+    pub unknowns: Vec<Unknown>,
+    pub quality_controls: Vec<QualityControlSample>,
+    pub standards: Vec<(f64, f64)>,         // concentration and mean of each group
+    pub standard_deviations: Vec<f64>,      // replicate scatter of each group
+    pub replicates: Vec<(f64, f64)>,        // concentration and value of each standard well
+    pub residuals: Vec<f64>,                // one per standard well
+    pub weights: Vec<f64>,                  // one per fitted point
+    pub variance_power: f64,                // theta of the power of mean weighting, if estimated
     pub sse: f64,
     pub mse: f64,
     pub rmse: f64,
     pub sy_x: f64,
-    pub r_sq: f64
+    pub r_sq: f64,
+    pub convergence: Convergence,
+    pub multi_start: Option<MultiStartReport>,
+    pub covariance: Vec<Vec<f64>>,             // of a, b, ln(c), d (and g), asymptotic
+    pub standard_errors: Vec<f64>,             // of a, b, c, d (and g)
+    pub confidence_intervals: Vec<(f64, f64)>, // 95%, of a, b, c, d (and g)
+    pub limits: Limits,
+    pub comparison: ModelComparison,
+    pub excluded: Vec<(String, String)>, // well and reason
+    pub potencies: Vec<Potency>,
+    pub dilution_series: Vec<DilutionSeries>,
+    pub unit: Unit,
+    pub acceptance: Acceptance,
 }
 
 impl Regression {
@@ -94,89 +473,160 @@ impl Regression {
         let unknowns_len = microplate.unknown_groups.len();
         let standards_len = microplate.standard_groups.len();
 
-        // (sum, count) pairs
-        let mut blank = (0.0, 0);
-        let mut control = (0.0, 0);
-        let mut unknowns = vec![(0.0, 0); unknowns_len];
-        let mut standards = vec![(0.0, 0); standards_len];
+        // Every group keeps its replicates
+        let mut blanks = Vec::new();
+        let mut controls = Vec::new();
+        let mut unknowns_replicates = vec![Vec::new(); unknowns_len];
+        let mut unknowns_dilutions = vec![Vec::new(); unknowns_len];
+        let mut standards_replicates = vec![Vec::new(); standards_len];
+        let mut quality_controls_replicates = vec![Vec::new(); microplate.quality_control_groups.len()];
 
         // add up values
-        for Sample { typ, group, value } in &microplate.samples {
+        let mut excluded = Vec::new();
+        for (i, Sample { typ, group, value, excluded: reason, dilution }) in microplate.samples.iter().enumerate() {
             if *typ == Unused { continue }
+            if let Some(reason) = reason {
+                excluded.push((microplate.well_name(i), reason.clone()));
+                continue
+            }
             let Some(value) = value else { return Err(UnassignedValue) };
             if !value.is_finite() { return Err(InvalidValue) }
 
             match typ {
                 Blank => {
-                    blank.0 += value;
-                    blank.1 += 1;
+                    blanks.push(*value);
                 },
                 Control => {
-                    control.0 += value;
-                    control.1 += 1;
+                    controls.push(*value);
                 },
                 Standard => {
-                    standards[*group].0 += value;
-                    standards[*group].1 += 1;
+                    standards_replicates[*group].push(*value);
                 },
                 Unknown => {
-                    unknowns[*group].0 += value;
-                    unknowns[*group].1 += 1;
+                    unknowns_replicates[*group].push(*value);
+                    unknowns_dilutions[*group].push(dilution.or(microplate.unknown_groups[*group].dilution).unwrap_or(1.0));
+                }
+                QualityControl => {
+                    quality_controls_replicates[*group].push(*value);
                 }
                 Unused => ()
             }
         }
 
-        let blank = if blank.1 != 0 { blank.0 / blank.1 as f64 } else { 0.0 };
-        let control = if control.1 != 0 { control.0 / control.1 as f64 } else { 0.0 };
+        let (blank, blank_sd) = if !blanks.is_empty() { mean_and_sd(&blanks) } else { (0.0, 0.0) };
+        let (control, control_sd) = if !controls.is_empty() { mean_and_sd(&controls) } else { (0.0, 0.0) };
 
-        let unknowns = unknowns.iter().enumerate().filter_map(|(i, &(sum, count))| {
-            if count == 0 { return None }
-            let measurement = sum / count as f64;
-            let label = microplate.unknown_groups[i].label.clone();
-            Some((0.0, measurement, label))
+        let unknowns = unknowns_replicates.into_iter().zip(unknowns_dilutions).enumerate().filter_map(|(i, (replicates, dilutions))| {
+            if replicates.is_empty() { return None }
+            let raw = replicates.iter().sum::<f64>() / replicates.len() as f64;
+            let Group { label, dilution, .. } = &microplate.unknown_groups[i];
+            Some(Unknown { label: label.clone(), raw, replicates, dilution: dilution.unwrap_or(1.0), dilutions, ..default() })
         }).collect();
 
+        let quality_controls = quality_controls_replicates.into_iter().enumerate()
+            .filter(|(_i, replicates)| !replicates.is_empty())
+            .map(|(i, replicates)| {
+                let Group { concentration, label, tolerance, .. } = &microplate.quality_control_groups[i];
+                let Some(nominal) = *concentration else { return Err(UnassignedConcentration) };
+                if !nominal.is_finite() { return Err(InvalidConcentration) }
+                let label = if label.is_empty() { format!("QC {}", i + 1) } else { label.clone() };
+                Ok(QualityControlSample { label, nominal, tolerance: *tolerance, replicates, ..default() })
+            })
+            .collect::<Result<_, _>>()?;
+
         let mut concentrations = vec![0.0; standards_len];
         for (i, group) in concentrations.iter_mut().enumerate() {
             let Some(concentration) = microplate.standard_groups[i].concentration else {
                 return Err(UnassignedConcentration)
             };
             if !concentration.is_finite() { return Err(InvalidConcentration) }
-            *group = concentration;
+            *group = match microplate.standard_groups[i].unit {
+                Some(unit) => convert(concentration, unit, microplate.unit, microplate.molecular_weight).ok_or(IncompatibleUnits)?,
+                None => concentration,
+            };
         }
 
-        let mut standards: Vec<_> = standards.iter().enumerate().filter_map(|(i, &(sum, count))| {
-            if count == 0 { return None }
+        // (concentration, mean, sd)
+        let mut standards: Vec<_> = standards_replicates.iter().enumerate().filter_map(|(i, values)| {
+            if values.is_empty() { return None }
             let concentration = concentrations[i];
-            let measurement = sum / count as f64;
-            Some((concentration, measurement))
+            let (measurement, sd) = mean_and_sd(values);
+            Some((concentration, measurement, sd))
         }).collect();
 
-        // We need at least 4 standards, preferably 8
-        if standards.len() < 4 { return Err(NotEnoughStandards) }
-
         // Sort standards by concentration
-        standards.sort_by(|(a_x, _a_y), (b_x, _b_y)| a_x.total_cmp(b_x));
+        standards.sort_by(|(a_x, _a_y, _), (b_x, _b_y, _)| a_x.total_cmp(b_x));
+
+        // We need at least as many concentrations as parameters, preferably 8
+        let model = microplate.model;
+        let concentration_count = standards.chunk_by(|(a_x, _, _), (b_x, _, _)| a_x == b_x).count();
+        if concentration_count < model.minimum_standards() { return Err(NotEnoughStandards) }
+        let standard_deviations = standards.iter().map(|&(_x, _y, sd)| sd).collect();
+        let standards: Vec<_> = standards.into_iter().map(|(x, y, _sd)| (x, y)).collect();
+
+        let mut replicates: Vec<_> = standards_replicates.iter().enumerate().flat_map(|(i, values)| {
+            let concentration = concentrations[i];
+            values.iter().map(move |&value| (concentration, value))
+        }).collect();
+        replicates.sort_by(|(a_x, _a_y), (b_x, _b_y)| a_x.total_cmp(b_x));
 
         // Find minimum measurement, this is not necessarily standards.first()
         let standard_min = standards.iter().min_by(|(_a_x, a_y), (_b_x, b_y)| a_y.total_cmp(b_y)).unwrap().1;
 
-        if control > standard_min { return Err(ControlTooBig) }
+        let standard_max = standards.iter().max_by(|(_a_x, a_y), (_b_x, b_y)| a_y.total_cmp(b_y)).unwrap().1;
+
+        let direction = match microplate.fit_options.direction {
+            Direction::Auto => Direction::detect(&standards),
+            direction => direction,
+        };
+        // The control is the 0-dose end of the curve, which is the top of a decreasing curve
+        if direction == Direction::Decreasing {
+            if !controls.is_empty() && control < standard_max { return Err(ControlTooSmall) }
+        } else if control > standard_min {
+            return Err(ControlTooBig)
+        }
         if blank > standard_min { return Err(BlankTooBig) }
 
-        
+        // Like the weights, the variance power is taken from the blank corrected responses
+        let variance_power = if microplate.fit_options.weighting == Weighting::EstimatedPower {
+            let corrected: Vec<Vec<f64>> = standards_replicates.iter().map(|values| values.iter().map(|v| v - blank).collect()).collect();
+            estimate_variance_power(&corrected).unwrap_or(0.0)
+        } else {
+            0.0
+        };
+
         let mut regression = Self {
+            model,
+            direction,
+            options: microplate.fit_options.clone(),
+            g: 1.0,
             blank,
+            blank_sd,
             control,
+            control_sd,
             unknowns,
+            quality_controls,
             standards,
+            standard_deviations,
+            replicates,
+            variance_power,
+            excluded,
+            unit: microplate.unit,
             ..default()
         };
-        
-        regression.four_pl_curve_fit();
+
+        regression.subtract_blank();
+        regression.calculate_weights();
+        regression.curve_fit();
         regression.calculate_unknowns();
+        regression.calculate_limits();
         regression.calculate_parameters();
+        regression.calculate_confidence_intervals();
+        regression.calculate_unknown_intervals();
+        regression.dilution_series = regression.aggregate_dilutions();
+        regression.acceptance = regression.check_acceptance(microplate.acceptance);
+        regression.comparison = regression.compare_models();
+        regression.potencies = regression.relative_potencies(microplate);
 
         Ok(regression)
     }
@@ -193,17 +643,79 @@ impl Regression {
         c * ((a - d) / (y - d) - 1.0).powf(1.0 / b)
     }
 
+    #[inline(always)]
+    pub fn five_pl(&self, x: f64) -> f64 {
+        let (a, b, c, d) = self.abcd;
+        d + ((a - d) / (1.0 + (x/c).powf(b)).powf(self.g))
+    }
+
+    #[inline(always)]
+    pub fn inverse_five_pl(&self, y: f64) -> f64 {
+        let (a, b, c, d) = self.abcd;
+        c * (((a - d) / (y - d)).powf(1.0 / self.g) - 1.0).powf(1.0 / b)
+    }
+
+    /// Evaluates the fitted curve of whichever model was used
+    #[inline(always)]
+    pub fn evaluate(&self, x: f64) -> f64 {
+        match self.model {
+            Model::FourPL => self.four_pl(x),
+            Model::FivePL => self.five_pl(x),
+            Model::Linear | Model::LogLog | Model::Quadratic => self.polynomial(x),
+            Model::CubicSpline | Model::PointToPoint => self.interpolate(x),
+        }
+    }
+
+    /// Backfits a measurement with whichever model was used
+    #[inline(always)]
+    pub fn inverse(&self, y: f64) -> f64 {
+        match self.model {
+            Model::FourPL => self.inverse_four_pl(y),
+            Model::FivePL => self.inverse_five_pl(y),
+            Model::Linear | Model::LogLog | Model::Quadratic => self.inverse_polynomial(y),
+            Model::CubicSpline | Model::PointToPoint => self.inverse_interpolate(y),
+        }
+    }
+
+    /// Degrees of freedom used up by the curve, every standard for the interpolating models
+    pub fn parameter_count(&self) -> usize {
+        if self.model.interpolates() {
+            self.standards.len()
+        } else {
+            self.model.parameter_count()
+        }
+    }
+
+    /// The points the curve is fitted to, either the standard means or every standard well
+    pub fn fit_points(&self) -> &[(f64, f64)] {
+        if self.options.fit_replicates {
+            &self.replicates
+        } else {
+            &self.standards
+        }
+    }
+
+    /// Weighted sum of squared residuals in the space the curve is fitted in
     #[inline(always)]
     pub fn sum_of_squares(&self) -> f64 {
-        self.standards.iter().map(|&(x, y)| {
-            let diff = y - self.four_pl(x);
-            diff * diff
+        let (points, weights) = self.fitted_points();
+        points.iter().zip(&weights).map(|(&(x, y), weight)| {
+            let diff = y - self.fitted_response(self.evaluate(x));
+            weight * diff * diff
+        }).sum()
+    }
+
+    /// Weighted sum of squared residuals of the response itself, comparable between models
+    pub fn response_sum_of_squares(&self) -> f64 {
+        self.fit_points().iter().zip(&self.weights).map(|(&(x, y), weight)| {
+            let diff = y - self.evaluate(x);
+            weight * diff * diff
         }).sum()
     }
     
     #[inline(always)]
     pub fn mean_squared_error(&self) -> f64 {
-        let length = self.standards.len() as f64;
+        let length = self.fitted_points().0.len() as f64;
         let sum_of_squares = self.sum_of_squares();
         sum_of_squares / length
     }
@@ -215,19 +727,23 @@ impl Regression {
 
     #[inline(always)]
     pub fn sy_x(&self) -> f64 {
-        let length = self.standards.len() as f64;
+        let length = self.fitted_points().0.len() as f64;
+        let parameters = self.parameter_count() as f64;
+        // An interpolation through the standard means has no degrees of freedom left
+        if length <= parameters { return f64::NAN }
         let sum_of_squares = self.sum_of_squares();
-        (sum_of_squares / (length - 4.0)).sqrt()
+        (sum_of_squares / (length - parameters)).sqrt()
     }
 
     #[inline(always)]
     pub fn r_squared(&self) -> f64 {
-        let n = self.standards.len() as f64;
-        let mean = self.standards.iter().map(|&(_x, y)| y).sum::<f64>() / n;
+        let (points, weights) = self.fitted_points();
+        let n: f64 = weights.iter().sum();
+        let mean = points.iter().zip(&weights).map(|(&(_x, y), weight)| weight * y).sum::<f64>() / n;
 
-        let total_sum_of_squares: f64 = self.standards.iter().map(|&(_x, y)| {
+        let total_sum_of_squares: f64 = points.iter().zip(&weights).map(|(&(_x, y), weight)| {
             let y_hat = y - mean;
-            y_hat * y_hat
+            weight * y_hat * y_hat
         }).sum();
 
 
@@ -237,12 +753,248 @@ impl Regression {
 
     #[inline(always)]
     pub fn calculate_unknowns(&mut self) {
-        let (a, b, c, d) = self.abcd;
-        for (x, y, _) in &mut self.unknowns {
-            *x = c * ((a - d) / (*y - d) - 1.0).powf(1.0 / b)
+        let mut unknowns = std::mem::take(&mut self.unknowns);
+        for unknown in &mut unknowns {
+            unknown.backfit = self.inverse(unknown.raw);
+            unknown.backfits = unknown.replicates.iter().map(|&y| self.inverse(y)).collect();
+            (unknown.mean, unknown.sd) = mean_and_sd(&unknown.backfits);
+            unknown.cv = unknown.sd / unknown.mean * 100.0;
+            // Wells with their own dilution are averaged after correcting for it
+            unknown.concentration = if unknown.dilutions.iter().all(|&dilution| dilution == unknown.dilution) {
+                unknown.backfit * unknown.dilution
+            } else {
+                let concentrations: Vec<_> = unknown.backfits.iter().zip(&unknown.dilutions).map(|(backfit, dilution)| backfit * dilution).collect();
+                mean_and_sd(&concentrations).0
+            };
+        }
+        self.unknowns = unknowns;
+
+        let mut quality_controls = std::mem::take(&mut self.quality_controls);
+        for quality_control in &mut quality_controls {
+            quality_control.backfits = quality_control.replicates.iter().map(|&y| self.inverse(y)).collect();
+        }
+        self.quality_controls = quality_controls;
+
+        self.residuals = self.replicates.iter().map(|&(x, y)| y - self.evaluate(x)).collect();
+    }
+
+    /// Weights of the fit points, from the responses after the blank is subtracted
+    pub fn calculate_weights(&mut self) {
+        let Self { options, standards, replicates, variance_power, .. } = self;
+        self.weights = if options.fit_replicates {
+            // Replicates are weighted by the mean of their group, a single well is too noisy
+            let points: Vec<_> = replicates.iter().map(|&(x, _y)| {
+                let i = standards.partition_point(|&(standard_x, _)| standard_x < x);
+                (x, standards[i].1)
+            }).collect();
+            options.weighting.weights(&points, *variance_power)
+        } else {
+            options.weighting.weights(standards, *variance_power)
+        };
+    }
+
+    pub fn subtract_blank(&mut self) {
+        let blank = self.blank;
+        for unknown in &mut self.unknowns {
+            unknown.raw -= blank;
+            unknown.replicates.iter_mut().for_each(|v| *v -= blank);
+        }
+        for quality_control in &mut self.quality_controls {
+            quality_control.replicates.iter_mut().for_each(|v| *v -= blank);
         }
+        self.standards.iter_mut().for_each(|(_, v)| *v -= blank);
+        self.replicates.iter_mut().for_each(|(_, v)| *v -= blank);
+        self.control -= blank;
     }
    
+    /// Names and values of the fitted parameters, g only for 5PL, none for the interpolating models
+    pub fn parameter_values(&self) -> Vec<(&'static str, f64)> {
+        let (a, b, c, d) = self.abcd;
+        let names: &[&'static str] = match self.model {
+            Model::FourPL | Model::FivePL => {
+                let mut parameters = vec![("a", a), ("b", b), ("c", c), ("d", d)];
+                if self.model == Model::FivePL { parameters.push(("g", self.g)) }
+                return parameters
+            }
+            Model::Linear => &["intercept", "slope"],
+            Model::LogLog => &["log intercept", "log slope"],
+            Model::Quadratic => &["b0", "b1", "b2"],
+            Model::CubicSpline | Model::PointToPoint => &[],
+        };
+        names.iter().copied().zip(self.coefficients.iter().copied()).collect()
+    }
+
+    // The parameters as the solver sees them, with c on a log scale
+    pub fn solver_parameters(&self) -> Vec<f64> {
+        match self.model {
+            Model::FourPL | Model::FivePL => {
+                let (a, b, c, d) = self.abcd;
+                let mut parameters = vec![a, b, c.ln(), d];
+                if self.model == Model::FivePL { parameters.push(self.g) }
+                parameters
+            }
+            Model::Linear | Model::LogLog | Model::Quadratic => self.coefficients.clone(),
+            Model::CubicSpline | Model::PointToPoint => Vec::new(),
+        }
+    }
+
+    pub fn calculate_confidence_intervals(&mut self) {
+        let parameters = self.solver_parameters();
+        let n = parameters.len();
+        let (points, weights) = self.fitted_points();
+        let degrees_of_freedom = points.len() as f64 - n as f64;
+        let t = t_quantile(0.975, degrees_of_freedom);
+        // Only the c of the logistic models is fitted on a log scale
+        let log_scale = |i: usize| self.model.is_logistic() && i == 2;
+
+        let model = self.model;
+        let information = information_matrix(&parameters, &points, &weights, |x, p, gradient| model.fitted_curve(x, p, gradient));
+        self.covariance = invert(&information)
+            .map(|inverse| inverse.into_iter().map(|row| row.into_iter().map(|v| v * self.sy_x * self.sy_x).collect()).collect())
+            .unwrap_or_else(|| vec![vec![f64::NAN; n]; n]);
+
+        match self.options.intervals {
+            IntervalMethod::Asymptotic => {
+                self.standard_errors = Vec::with_capacity(n);
+                self.confidence_intervals = Vec::with_capacity(n);
+                for (i, &value) in parameters.iter().enumerate() {
+                    let se = self.covariance[i][i].sqrt();
+                    if log_scale(i) {
+                        // c is fitted on a log scale, so its interval is asymmetric
+                        let c = value.exp();
+                        self.standard_errors.push(c * se);
+                        self.confidence_intervals.push(((value - t * se).exp(), (value + t * se).exp()));
+                    } else {
+                        self.standard_errors.push(se);
+                        self.confidence_intervals.push((value - t * se, value + t * se));
+                    }
+                }
+            }
+            // Thousands of refits take too long for the UI thread, see bootstrap_intervals
+            IntervalMethod::Bootstrap { .. } => {
+                self.standard_errors.clear();
+                self.confidence_intervals.clear();
+            }
+        }
+    }
+
+    /// Standard errors and 95% confidence intervals of the parameters from a residual bootstrap.
+    /// `progress` counts the finished resamples, so that it can run on another thread.
+    pub fn bootstrap_intervals(&self, resamples: usize, seed: u64, progress: &AtomicUsize) -> ParameterIntervals {
+        let n = self.solver_parameters().len();
+        let samples = if n > 0 { self.bootstrap(resamples, seed, progress) } else { Vec::new() };
+        let log_scale = |i: usize| self.model.is_logistic() && i == 2;
+
+        (0..n).map(|i| {
+            let mut values: Vec<_> = samples.iter()
+                .map(|sample| if log_scale(i) { sample[i].exp() } else { sample[i] })
+                .filter(|v| v.is_finite())
+                .collect();
+            values.sort_by(f64::total_cmp);
+            let (_, sd) = mean_and_sd(&values);
+            (sd, (percentile(&values, 0.025), percentile(&values, 0.975)))
+        }).unzip()
+    }
+
+    /// Inverse prediction intervals of the backfits by the delta method, on a log scale like c.
+    /// The variance of the mean response comes from the replicates for the confidence interval,
+    /// and from Sy.x and the weighting for the prediction interval.
+    pub fn calculate_unknown_intervals(&mut self) {
+        let parameters = self.solver_parameters();
+        let degrees_of_freedom = self.fitted_points().0.len() as f64 - self.parameter_count() as f64;
+        let t = t_quantile(0.975, degrees_of_freedom);
+
+        // Everything is in the space the curve is fitted in, where Sy.x and the covariance are
+        let mut unknowns = std::mem::take(&mut self.unknowns);
+        for unknown in &mut unknowns {
+            let x = unknown.backfit.ln();
+            let mut gradient = vec![0.0; parameters.len()];
+            self.model.fitted_curve(unknown.backfit, &parameters, &mut gradient);
+
+            // Variance of the curve at x from the parameter covariance, the interpolating models have none
+            let curve_variance: f64 = gradient.iter().zip(&self.covariance)
+                .map(|(gi, row)| gi * row.iter().zip(&gradient).map(|(cell, gj)| cell * gj).sum::<f64>())
+                .sum();
+            // Slope against ln(x), central difference
+            let h = 1e-4;
+            let fitted = |x: f64| self.fitted_response(self.evaluate(x.exp()));
+            let slope = (fitted(x + h) - fitted(x - h)) / (2.0 * h);
+
+            let replicates = unknown.replicates.len() as f64;
+            let responses: Vec<_> = unknown.replicates.iter().map(|&y| self.fitted_response(y)).collect();
+            let (_, sd) = mean_and_sd(&responses);
+            let residual_variance = self.sy_x * self.sy_x / self.relative_weight(unknown.backfit, unknown.raw);
+            // A single well has no scatter of its own
+            let observed_variance = if unknown.replicates.len() > 1 { sd * sd } else { residual_variance };
+
+            let interval = |response_variance: f64| {
+                let se = ((curve_variance + response_variance / replicates) / (slope * slope)).sqrt();
+                if se.is_finite() { ((x - t * se).exp(), (x + t * se).exp()) } else { (f64::NAN, f64::NAN) }
+            };
+            unknown.confidence_interval = interval(observed_variance);
+            unknown.prediction_interval = interval(residual_variance);
+        }
+        self.unknowns = unknowns;
+    }
+
+    // Weight of a response at (x, y) relative to the mean weight of the standards
+    fn relative_weight(&self, x: f64, y: f64) -> f64 {
+        let mut points = self.standards.clone();
+        points.push((x, y));
+        let weights = self.options.weighting.weights(&points, self.variance_power);
+        let mean = weights[..self.standards.len()].iter().sum::<f64>() / self.standards.len() as f64;
+        weights[self.standards.len()] / mean
+    }
+
+    /// Refits the curve to the fitted values plus resampled residuals, returns the solver parameters of every resample
+    pub fn bootstrap(&self, resamples: usize, seed: u64, progress: &AtomicUsize) -> Vec<Vec<f64>> {
+        let mut rng = SplitMix64(seed);
+        // Residuals are taken in the space the curve is fitted in, and scaled by the weights, so that they are exchangeable
+        let fitted: Vec<_> = self.fit_points().iter().map(|&(x, _y)| self.fitted_response(self.evaluate(x))).collect();
+        let (points, weights) = self.fitted_points();
+        let residuals: Vec<_> = points.iter().zip(&weights)
+            .map(|(&(x, y), w)| (y - self.fitted_response(self.evaluate(x))) * w.sqrt())
+            .collect();
+
+        let mut template = self.clone();
+        template.options.multi_start.enabled = false;
+
+        (0..resamples).map(|_| {
+            let mut resample = template.clone();
+            let points = if resample.options.fit_replicates { &mut resample.replicates } else { &mut resample.standards };
+            for ((point, f), w) in points.iter_mut().zip(&fitted).zip(&self.weights) {
+                point.1 = self.response(f + residuals[rng.next_index(residuals.len())] / w.sqrt());
+            }
+            resample.curve_fit();
+            progress.fetch_add(1, Ordering::Relaxed);
+            resample.solver_parameters()
+        }).collect()
+    }
+
+    pub fn curve_fit(&mut self) {
+        match self.model {
+            Model::FourPL => self.four_pl_curve_fit(),
+            Model::FivePL => self.five_pl_curve_fit(),
+            Model::Linear | Model::LogLog | Model::Quadratic => self.polynomial_curve_fit(),
+            Model::CubicSpline | Model::PointToPoint => self.interpolation_fit(),
+        }
+    }
+
+    pub fn direction_description(&self) -> String {
+        if self.options.direction == Direction::Auto {
+            format!("Direction: {} (detected)", self.direction.name())
+        } else {
+            format!("Direction: {}", self.direction.name())
+        }
+    }
+
+    pub fn weighting_description(&self) -> String {
+        match self.options.weighting {
+            Weighting::EstimatedPower => format!("Weighting: 1/Y^{:.3} (power of mean)", self.variance_power),
+            weighting => format!("Weighting: {}", weighting.name()),
+        }
+    }
+
     pub fn calculate_parameters(&mut self) {
         self.sse = self.sum_of_squares();
         self.mse = self.mean_squared_error();
@@ -252,35 +1004,34 @@ impl Regression {
     }
     
     pub fn four_pl_curve_fit(&mut self) {
-        let Self { blank, unknowns, standards, control, .. } = self;
-        let n = standards.len() as f64;
-
-        // subtract blank
-        unknowns.iter_mut().for_each(|(_, v, _)| *v -= *blank);
-        standards.iter_mut().for_each(|(_, v)| *v -= *blank);
-        *control -= *blank;
-
         // convert standards x to x hat
-        let standards: Vec<_> = standards.iter().map(|&(x, y)| (x.ln(), y)).collect();
+        let points: Vec<_> = self.fit_points().iter().map(|&(x, y)| (x.ln(), y)).collect();
+        let standards: Vec<_> = self.standards.iter().map(|&(x, y)| (x.ln(), y)).collect();
+        let (a_min, a_max) = self.zero_dose_bounds();
+        let decreasing = self.direction == Direction::Decreasing;
+        let Self { control, weights, .. } = self;
 
         // find the minimum and maximum measurement, this is not necessarily standards.first()
-        let min = standards.iter().min_by(|(_a_x, a_y), (_b_x, b_y)| a_y.total_cmp(b_y)).unwrap();
-        let max = standards.iter().max_by(|(_a_x, a_y), (_b_x, b_y)| a_y.total_cmp(b_y)).unwrap();
+        let min = standards.iter().min_by(|(_a_x, a_y), (_b_x, b_y)| a_y.total_cmp(b_y)).unwrap().1;
+        let max = standards.iter().max_by(|(_a_x, a_y), (_b_x, b_y)| a_y.total_cmp(b_y)).unwrap().1;
 
-
-        // guess initial values
-        let mut a = *control; // 0-dose asymptote
-        let mut b = 1.0;      // slope at IC50
-        let mut d = max.1;    // inf-dose asymptote
+        // guess initial values, a is the 0-dose asymptote, d the inf-dose asymptote
+        let b = 1.0; // slope at IC50
+        let (a, d) = if decreasing {
+            (control.max(max), min)
+        } else {
+            (*control, max)
+        };
 
         // We assume the point of inflection, c, is close to the interpolation between two standards with the greatest slope
+        let sign = if decreasing { -1.0 } else { 1.0 };
         let mut c_incline = 0.0;
         let mut c = 0.0;
         for window in standards.windows(2) {
             let a = window[0];
             let b = window[1];
 
-            let incline = (b.1 - a.1) / (b.0 - a.0);
+            let incline = sign * (b.1 - a.1) / (b.0 - a.0);
 
             if c_incline < incline {
                 c_incline = incline;
@@ -288,53 +1039,278 @@ impl Regression {
             }
         }
 
-        dbg!(a, b, c, d, blank, *control, min);
+        let fit = |parameters: &mut [f64]| {
+            LevenbergMarquardt::default().minimize(
+                parameters,
+                &points,
+                weights,
+                |x, p, gradient| log_logistic(x, p[0], p[1], p[2], p[3], 1.0, gradient),
+                // We can make the reasonable assumption that the 0-dose asymptote must be between the control and the first standard
+                |p| p[0] = p[0].clamp(a_min, a_max),
+            )
+        };
+        let sum_of_squares = |[a, b, c, d]: [f64; 4]| -> f64 {
+            points.iter().zip(weights.iter()).map(|(&(x, y), weight)| {
+                let diff = y - log_logistic(x, a, b, c, d, 1.0, &mut [0.0; 4]);
+                weight * diff * diff
+            }).sum()
+        };
+
+        let mut parameters = [a, b, c, d];
+        let mut convergence = fit(&mut parameters);
+
+        let multi_start = self.options.multi_start;
+        self.multi_start = None;
+        if multi_start.enabled && multi_start.starts > 0 {
+            let MultiStart { starts, spread, seed, .. } = multi_start;
+            let range = max - min;
+            let x_min = standards.first().unwrap().0;
+            let x_max = standards.last().unwrap().0;
+            let x_range = x_max - x_min;
+
+            // a, ln(b), ln(c), d
+            let (a_range, d_range) = if decreasing {
+                ((a_min, (max + spread * range).min(a_max)), (min - spread * range, min))
+            } else {
+                (((min - spread * range).max(a_min), a_max), (max, max + spread * range))
+            };
+            let ranges = [
+                a_range,
+                (-2.0 * spread * 2f64.ln(), 2.0 * spread * 2f64.ln()),
+                (x_min - spread * x_range / 2.0, x_max + spread * x_range / 2.0),
+                d_range,
+            ];
+            let samples = latin_hypercube(ranges, starts, seed);
+
+            let mut best_sse = sum_of_squares(parameters);
+            let mut report = MultiStartReport {
+                starts,
+                spread,
+                seed,
+                ranges: [ranges[0], (ranges[1].0.exp(), ranges[1].1.exp()), (ranges[2].0.exp(), ranges[2].1.exp()), ranges[3]],
+                ..default()
+            };
+
+            for (i, [a, b, c, d]) in samples.into_iter().enumerate() {
+                let mut candidate = [a, b.exp(), c, d];
+                let candidate_convergence = fit(&mut candidate);
+                let sse = sum_of_squares(candidate);
+
+                report.seeds.push((a, b.exp(), c.exp(), d));
+                report.sse.push(sse);
+
+                // Only take the start if it is an improvement over the heuristic guess
+                if sse < best_sse {
+                    best_sse = sse;
+                    parameters = candidate;
+                    convergence = candidate_convergence;
+                    report.best_start = Some(i);
+                }
+            }
+            self.multi_start = Some(report);
+        }
+
+        let [a, b, c, d] = parameters;
+        self.convergence = convergence;
+        self.abcd = (a, b, c.exp(), d);
+    }
+
+    // Bounds of the 0-dose asymptote a, between the control and the nearest standard.
+    // A decreasing curve without control wells has no upper bound.
+    fn zero_dose_bounds(&self) -> (f64, f64) {
+        let min = self.standards.iter().min_by(|(_a_x, a_y), (_b_x, b_y)| a_y.total_cmp(b_y)).unwrap().1;
+        let max = self.standards.iter().max_by(|(_a_x, a_y), (_b_x, b_y)| a_y.total_cmp(b_y)).unwrap().1;
+        match self.direction {
+            Direction::Decreasing => (max, if self.control >= max { self.control } else { f64::INFINITY }),
+            _ => (self.control, min),
+        }
+    }
+
+    pub fn five_pl_curve_fit(&mut self) {
+        // Start from the symmetric fit, g = 1 is exactly the 4PL curve
+        self.four_pl_curve_fit();
+
+        let points: Vec<_> = self.fit_points().iter().map(|&(x, y)| (x.ln(), y)).collect();
+        let (a_min, a_max) = self.zero_dose_bounds();
+
+        let (a, b, c, d) = self.abcd;
+        let mut parameters = [a, b, c.ln(), d, 1.0];
+        self.convergence = LevenbergMarquardt::default().minimize(
+            &mut parameters,
+            &points,
+            &self.weights,
+            |x, p, gradient| log_logistic(x, p[0], p[1], p[2], p[3], p[4], gradient),
+            |p| {
+                p[0] = p[0].clamp(a_min, a_max);
+                // The asymmetry has to stay positive, otherwise the curve flips
+                p[4] = p[4].clamp(0.05, 20.0);
+            },
+        );
+
+        let [a, b, c, d, g] = parameters;
+        self.abcd = (a, b, c.exp(), d);
+        self.g = g;
+    }
+}
 
+/// Mean and sample standard deviation, the deviation is 0 for a single value
+pub fn mean_and_sd(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 { return (mean, 0.0) }
+    let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1.0);
+    (mean, variance.sqrt())
+}
 
-        let learn_rate = (0.1, 1.0, 1.0, 0.1);
+/// The 5PL curve over x hat = ln(x) with c hat = ln(c), which is the 4PL curve for g = 1.
+/// Writes the partial derivatives for a, b, c hat, d (and g, if there is room) into `gradient`.
+pub fn log_logistic(x: f64, a: f64, b: f64, c: f64, d: f64, g: f64, gradient: &mut [f64]) -> f64 {
+    let t = b * (x - c);
+    // 1 / (1 + e^t) and ln(1 + e^t) without overflowing for large t
+    let (sigmoid, softplus) = if t > 0.0 {
+        let e = (-t).exp();
+        (e / (1.0 + e), t + e.ln_1p())
+    } else {
+        let e = t.exp();
+        (1.0 / (1.0 + e), e.ln_1p())
+    };
+    let sigmoid_g = (-g * softplus).exp();
+    // e^t * sigmoid^(g + 1), rewritten so it stays finite
+    let slope = sigmoid_g * (1.0 - sigmoid);
 
-        // I should really fix this
-        for i in 0..100_000 {
-            let mut sum_a = 0.0;
-            let mut sum_b = 0.0;
-            let mut sum_c = 0.0;
-            let mut sum_d = 0.0;
+    gradient[0] = sigmoid_g;
+    gradient[1] = -(a - d) * g * (x - c) * slope;
+    gradient[2] = (a - d) * g * b * slope;
+    gradient[3] = 1.0 - sigmoid_g;
+    if let Some(dg) = gradient.get_mut(4) {
+        *dg = -(a - d) * sigmoid_g * softplus;
+    }
 
-            for (x, y) in standards.iter() {
-                let ebxc = (b * (x - c)).exp();
-                let sigmoid = 1.0 / (1.0 + ebxc);
+    d + (a - d) * sigmoid_g
+}
 
-                let diff = y - d - (a - d) * sigmoid;
-                let duda = sigmoid;
-                let dudb = (x - c) * ebxc * sigmoid * sigmoid;
-                let dudc = ebxc * sigmoid * sigmoid;
-                let dudd = sigmoid;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                sum_a += diff * duda;
-                sum_b += diff * dudb;
-                sum_c += diff * dudc;
-                sum_d += diff * dudd;
+    // Standards on a 4PL curve rising from 0.1 to 2.5 around 150, in duplicate with a little noise
+    fn microplate() -> Microplate {
+        let curve = |x: f64| 0.1 + (2.5 - 0.1) / (1.0 + (x / 150.0).powf(-1.3));
+        let noise = [0.01, -0.02, 0.015, -0.005, 0.0, 0.02, -0.01, 0.005];
+        let concentrations = [1000.0, 500.0, 250.0, 125.0, 62.5, 31.25, 15.6, 7.8];
+        let mut microplate = Microplate::new(12, 8);
+        microplate.standard_groups = concentrations.iter().map(|&x| Group { concentration: Some(x), ..default() }).collect();
+        for (group, &x) in concentrations.iter().enumerate() {
+            for replicate in 0..2 {
+                let sample = &mut microplate.samples[replicate * 8 + group];
+                (sample.typ, sample.group) = (Standard, group);
+                sample.value = Some(curve(x) * (1.0 + noise[(group * 2 + replicate) % noise.len()]));
             }
+        }
+        microplate
+    }
 
-            let da = -2.0 / n * sum_a;
-            let db = 2.0 * (a - d) / n * sum_b;
-            let dc = -2.0 * b * (a - d) / n * sum_c;
-            let dd = -2.0 / n * sum_d;
-            
-            a -= learn_rate.0 * da;
-            b -= learn_rate.1 * db;
-            c -= learn_rate.2 * dc;
-            d -= learn_rate.3 * dd;
+    #[test]
+    fn asymptotic_intervals_around_the_parameters() {
+        let regression = Regression::new(&microplate()).unwrap();
+        let (a, b, c, d) = regression.abcd;
+        assert_eq!(regression.standard_errors.len(), 4);
 
-            // We can make the reasonable assumption that the asymptotic lower bound must be between the control and the first standard
-            a = a.clamp(*control, min.1);
+        let t = t_quantile(0.975, 8.0 - 4.0);
+        for (i, value) in [a, b, c, d].into_iter().enumerate() {
+            let (low, high) = regression.confidence_intervals[i];
+            assert!(low < value && value < high, "{i}: {low} {value} {high}");
+            assert!(regression.standard_errors[i] > 0.0);
+            // c is fitted on a log scale, its interval is symmetric around ln(c)
+            if i == 2 {
+                assert!(((high / value).ln() - (value / low).ln()).abs() < 1e-9);
+            } else {
+                assert!(((high - low) / 2.0 - t * regression.standard_errors[i]).abs() < 1e-9);
+            }
+        }
+        assert!((c - 150.0).abs() / 150.0 < 0.1);
+    }
 
-            if i % 1000 == 0 { println!("a: {}, b: {}, c: {}, d: {}", a, b, c, d) };
+    #[test]
+    fn intervals_grow_with_the_scatter() {
+        let mut noisy = microplate();
+        for (i, sample) in noisy.samples.iter_mut().enumerate() {
+            if let Some(value) = &mut sample.value { *value *= if i % 2 == 0 { 1.05 } else { 0.95 } }
+        }
+        let quiet = Regression::new(&microplate()).unwrap();
+        let noisy = Regression::new(&noisy).unwrap();
+        for (quiet, noisy) in quiet.standard_errors.iter().zip(&noisy.standard_errors) {
+            assert!(noisy > quiet);
         }
+    }
+
+    #[test]
+    fn bootstrap_is_reproducible() {
+        let mut microplate = microplate();
+        microplate.fit_options.intervals = IntervalMethod::Bootstrap { resamples: 200, seed: 1 };
+        let regression = Regression::new(&microplate).unwrap();
+        // Left to bootstrap_intervals, which runs off the UI thread
+        assert!(regression.standard_errors.is_empty() && regression.confidence_intervals.is_empty());
 
+        let progress = AtomicUsize::new(0);
+        let (standard_errors, confidence_intervals) = regression.bootstrap_intervals(200, 1, &progress);
+        assert_eq!(progress.load(Ordering::Relaxed), 200);
+        assert_eq!(standard_errors.len(), 4);
+        let (a, b, c, d) = regression.abcd;
+        for (&(low, high), value) in confidence_intervals.iter().zip([a, b, c, d]) {
+            assert!(low <= value && value <= high, "{low} {value} {high}");
+        }
+        assert_eq!(regression.bootstrap_intervals(200, 1, &AtomicUsize::new(0)), (standard_errors, confidence_intervals));
+    }
 
-        let c = c.exp();
+    #[test]
+    fn estimates_a_known_variance_power() {
+        // Two replicates spread by mean^(theta / 2), so the variance goes with mean^theta
+        let groups: Vec<_> = [0.1, 0.3, 0.9, 2.7].iter().map(|&mean: &f64| {
+            let spread = 0.05 * mean.powf(1.5 / 2.0);
+            vec![mean - spread, mean + spread]
+        }).collect();
+        assert!((estimate_variance_power(&groups).unwrap() - 1.5).abs() < 1e-9);
 
-        self.abcd = (a, b, c, d);
+        assert_eq!(estimate_variance_power(&groups[..1]), None);
+        assert_eq!(estimate_variance_power(&[vec![1.0], vec![2.0], vec![3.0]]), None);
+    }
+
+    #[test]
+    fn weights_by_the_variance_model() {
+        let points = [(10.0, 0.5), (20.0, 1.0), (40.0, 2.0)];
+        let close = |a: Vec<f64>, b: [f64; 3]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-12);
+        // Normalized to a mean of 1
+        assert!(close(Weighting::None.weights(&points, 0.0), [1.0, 1.0, 1.0]));
+        assert!(close(Weighting::InverseY.weights(&points, 0.0), [12.0 / 7.0, 6.0 / 7.0, 3.0 / 7.0]));
+        assert!(close(Weighting::InverseYSquared.weights(&points, 0.0), [48.0 / 21.0, 12.0 / 21.0, 3.0 / 21.0]));
+        assert!(close(Weighting::InverseXSquared.weights(&points, 0.0), [48.0 / 21.0, 12.0 / 21.0, 3.0 / 21.0]));
+        assert!(close(Weighting::EstimatedPower.weights(&points, 1.0), [12.0 / 7.0, 6.0 / 7.0, 3.0 / 7.0]));
+    }
+
+    #[test]
+    fn weights_come_from_blank_corrected_responses() {
+        let mut microplate = microplate();
+        for sample in &mut microplate.samples[16..18] {
+            (sample.typ, sample.value) = (Blank, Some(0.08));
+        }
+        for weighting in [Weighting::InverseY, Weighting::InverseYSquared] {
+            microplate.fit_options.weighting = weighting;
+            let regression = Regression::new(&microplate).unwrap();
+
+            // The means of the standard wells, less the blank
+            let points: Vec<_> = microplate.standard_groups.iter().enumerate().map(|(group, standard)| {
+                let values: Vec<_> = microplate.samples.iter()
+                    .filter(|sample| sample.typ == Standard && sample.group == group)
+                    .filter_map(|sample| sample.value)
+                    .collect();
+                (standard.concentration.unwrap(), values.iter().sum::<f64>() / values.len() as f64 - 0.08)
+            }).rev().collect();
+            let expected = weighting.weights(&points, 0.0);
+            assert_eq!(regression.weights.len(), expected.len());
+            for (weight, expected) in regression.weights.iter().zip(&expected) {
+                assert!((weight - expected).abs() < 1e-9, "{:?} {expected:?}", regression.weights);
+            }
+        }
     }
 }
@@ -1,8 +1,25 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod acceptance;
 mod app;
+mod dilution;
+mod history;
+mod import;
+mod layout;
+mod levenberg_marquardt;
+mod limits;
 mod logistic_regression;
+mod model_comparison;
+mod models;
+mod multi_start;
+mod outliers;
+mod potency;
+mod project;
+mod readers;
+mod statistics;
+mod templates;
 mod ui;
+mod units;
 
 use crate::app::*;
 use eframe::egui::{self, IconData};
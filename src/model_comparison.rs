@@ -0,0 +1,88 @@
+use crate::{logistic_regression::*, statistics::*};
+
+/// Goodness of fit of one candidate model, fitted to the same points as the others
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub model: Model,
+    pub sse: f64,
+    pub sy_x: f64,
+    pub aicc: f64, // Akaike information criterion, corrected for small samples
+    pub bic: f64,  // Bayesian information criterion
+    pub converged: bool,
+}
+
+/// Extra sum of squares F-test of a model against a simpler model nested in it
+#[derive(Clone, Debug)]
+pub struct FTest {
+    pub simpler: Model,
+    pub complex: Model,
+    pub f: f64,
+    pub p_value: f64, // small values mean the extra parameters improve the fit
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ModelComparison {
+    pub candidates: Vec<Candidate>,
+    pub f_tests: Vec<FTest>,
+    pub recommended: Option<Model>, // lowest AICc of the converged candidates
+}
+
+// The first model is the second one with a parameter fixed, g = 1 or a zero quadratic term
+const NESTED: [(Model, Model); 2] = [(Model::FourPL, Model::FivePL), (Model::Linear, Model::Quadratic)];
+
+impl Regression {
+    /// Fits every model with parameters to the fit points of this regression.
+    /// The interpolating models are left out, they have no residual degrees of freedom.
+    pub fn compare_models(&self) -> ModelComparison {
+        let n = self.fit_points().len() as f64;
+
+        let candidates: Vec<_> = Model::ALL.into_iter()
+            .filter(|model| !model.interpolates())
+            // AICc needs more points than parameters + 2
+            .filter(|model| n > model.parameter_count() as f64 + 2.0)
+            .map(|model| {
+                let fit = if model == self.model {
+                    self.clone()
+                } else {
+                    let mut fit = self.clone();
+                    fit.model = model;
+                    fit.g = 1.0;
+                    fit.curve_fit();
+                    fit
+                };
+
+                // On the scale of the response, log-log is fitted on another one
+                let sse = fit.response_sum_of_squares();
+                // The residual variance counts as a parameter too
+                let k = model.parameter_count() as f64 + 1.0;
+                let log_likelihood = n * (sse / n).ln();
+                Candidate {
+                    model,
+                    sse,
+                    sy_x: (sse / (n - model.parameter_count() as f64)).sqrt(),
+                    aicc: log_likelihood + 2.0 * k + 2.0 * k * (k + 1.0) / (n - k - 1.0),
+                    bic: log_likelihood + k * n.ln(),
+                    converged: fit.convergence.is_converged(),
+                }
+            })
+            .collect();
+
+        let f_tests = NESTED.iter().filter_map(|&(simpler, complex)| {
+            let simpler_fit = candidates.iter().find(|candidate| candidate.model == simpler)?;
+            let complex_fit = candidates.iter().find(|candidate| candidate.model == complex)?;
+            let simpler_df = n - simpler.parameter_count() as f64;
+            let complex_df = n - complex.parameter_count() as f64;
+
+            let f = ((simpler_fit.sse - complex_fit.sse) / (simpler_df - complex_df)) / (complex_fit.sse / complex_df);
+            let p_value = 1.0 - f_cdf(f, simpler_df - complex_df, complex_df);
+            Some(FTest { simpler, complex, f, p_value })
+        }).collect();
+
+        let recommended = candidates.iter()
+            .filter(|candidate| candidate.converged && candidate.aicc.is_finite())
+            .min_by(|a, b| a.aicc.total_cmp(&b.aicc))
+            .map(|candidate| candidate.model);
+
+        ModelComparison { candidates, f_tests, recommended }
+    }
+}
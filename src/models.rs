@@ -0,0 +1,249 @@
+use crate::{levenberg_marquardt::*, logistic_regression::*};
+
+// Standard curves besides the logistic ones. The interpolating models work on ln(x), like the plot.
+
+impl Regression {
+    pub fn polynomial_curve_fit(&mut self) {
+        let model = self.model;
+        let (points, weights) = self.fitted_points();
+
+        // The problem is linear, so the solver does not need a good initial guess
+        let mut parameters = vec![0.0; model.parameter_count()];
+        self.convergence = LevenbergMarquardt::default().minimize(
+            &mut parameters,
+            &points,
+            &weights,
+            |x, p, gradient| model.fitted_curve(x, p, gradient),
+            |_p| (),
+        );
+        self.coefficients = parameters;
+    }
+
+    /// The fit points and their weights in the space the curve is fitted in, see `Model::fitted_curve`.
+    /// Log-log is a straight line through log10(x) and log10(y), which only exists for positive values.
+    pub fn fitted_points(&self) -> (Vec<(f64, f64)>, Vec<f64>) {
+        let model = self.model;
+        self.fit_points().iter().zip(&self.weights)
+            .filter(|&(&(x, y), _weight)| model != Model::LogLog || (x > 0.0 && y > 0.0))
+            .map(|(&(x, y), &weight)| ((x, self.fitted_response(y)), weight))
+            .unzip()
+    }
+
+    /// A response in the space the curve is fitted in
+    #[inline(always)]
+    pub fn fitted_response(&self, y: f64) -> f64 {
+        if self.model == Model::LogLog { y.log10() } else { y }
+    }
+
+    /// The response of a value in the space the curve is fitted in, the inverse of `fitted_response`
+    #[inline(always)]
+    pub fn response(&self, fitted: f64) -> f64 {
+        if self.model == Model::LogLog { 10f64.powf(fitted) } else { fitted }
+    }
+
+    #[inline(always)]
+    pub fn polynomial(&self, x: f64) -> f64 {
+        self.model.curve(x, &self.coefficients, &mut [0.0; 3])
+    }
+
+    pub fn inverse_polynomial(&self, y: f64) -> f64 {
+        let p = &self.coefficients;
+        match self.model {
+            Model::LogLog => 10f64.powf((y.log10() - p[0]) / p[1]),
+            Model::Quadratic => {
+                // Roots of p2 x^2 + p1 x + p0 - y, in the form that does not cancel out
+                let (a, b, c) = (p[2], p[1], p[0] - y);
+                let discriminant = b * b - 4.0 * a * c;
+                if discriminant < 0.0 { return f64::NAN }
+                let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+                let (x_min, x_max) = (self.standards.first().unwrap().0, self.standards.last().unwrap().0);
+                let increasing = self.direction != Direction::Decreasing;
+
+                // Prefer the root on the side of the parabola that goes in the direction of the standards,
+                // and then the one closest to them
+                [q / a, c / q].into_iter()
+                    .filter(|x| x.is_finite() && *x > 0.0)
+                    .min_by(|&u, &v| {
+                        let wrong_side = |x: f64| (2.0 * a * x + b > 0.0) != increasing;
+                        let distance = |x: f64| (x_min - x).max(x - x_max).max(0.0);
+                        (wrong_side(u), distance(u)).partial_cmp(&(wrong_side(v), distance(v))).unwrap()
+                    })
+                    .unwrap_or(f64::NAN)
+            }
+            _ => (y - p[0]) / p[1],
+        }
+    }
+
+    /// Nothing to fit, the spline only needs its knots and the second derivatives at them
+    pub fn interpolation_fit(&mut self) {
+        self.knots = self.knots();
+        self.coefficients = if self.model == Model::CubicSpline {
+            natural_spline(&self.knots)
+        } else {
+            Vec::new()
+        };
+        self.convergence = Convergence::Interpolated;
+    }
+
+    // Standard groups with the same concentration are merged into one knot, the segments between them would be empty
+    fn knots(&self) -> Vec<(f64, f64)> {
+        self.standards.chunk_by(|(a_x, _), (b_x, _)| a_x == b_x).map(|group| {
+            let y = group.iter().map(|&(_x, y)| y).sum::<f64>() / group.len() as f64;
+            (group[0].0.ln(), y)
+        }).collect()
+    }
+
+    /// Evaluates the spline or the straight lines between the standards, outside of them they continue in a straight line
+    pub fn interpolate(&self, x: f64) -> f64 {
+        let knots = &self.knots;
+        let u = x.ln();
+        if u.is_nan() { return f64::NAN }
+        // The segment u lies on, or the first or last one
+        let i = knots.partition_point(|&(knot, _y)| knot <= u).clamp(1, knots.len() - 1) - 1;
+        let ((u0, y0), (u1, y1)) = (knots[i], knots[i + 1]);
+
+        if self.model == Model::PointToPoint {
+            return y0 + (y1 - y0) * (u - u0) / (u1 - u0)
+        }
+
+        let end = u.clamp(knots[0].0, knots[knots.len() - 1].0);
+        let (value, slope) = self.spline_segment(i, end);
+        value + slope * (u - end)
+    }
+
+    // Value and slope of the spline on segment i at u
+    fn spline_segment(&self, i: usize, u: f64) -> (f64, f64) {
+        let m = &self.coefficients;
+        let ((u0, y0), (u1, y1)) = (self.knots[i], self.knots[i + 1]);
+        let h = u1 - u0;
+        let (left, right) = (u1 - u, u - u0);
+        let value = m[i] * left.powi(3) / (6.0 * h) + m[i + 1] * right.powi(3) / (6.0 * h)
+            + (y0 / h - m[i] * h / 6.0) * left + (y1 / h - m[i + 1] * h / 6.0) * right;
+        let slope = -m[i] * left * left / (2.0 * h) + m[i + 1] * right * right / (2.0 * h)
+            - (y0 / h - m[i] * h / 6.0) + (y1 / h - m[i + 1] * h / 6.0);
+        (value, slope)
+    }
+
+    /// Backfits within the range of the standards only, the first segment that contains y wins
+    pub fn inverse_interpolate(&self, y: f64) -> f64 {
+        for (i, window) in self.knots.windows(2).enumerate() {
+            let ((u0, y0), (u1, y1)) = (window[0], window[1]);
+            if !(y0.min(y1)..=y0.max(y1)).contains(&y) { continue }
+
+            if self.model == Model::PointToPoint {
+                if y0 == y1 { return u0.exp() }
+                return (u0 + (u1 - u0) * (y - y0) / (y1 - y0)).exp()
+            }
+
+            // Bisection, the spline goes through y somewhere on this segment
+            let (mut low, mut high) = (u0, u1);
+            let rising = y1 > y0;
+            for _ in 0..100 {
+                let middle = (low + high) / 2.0;
+                if (self.spline_segment(i, middle).0 < y) == rising {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            return ((low + high) / 2.0).exp()
+        }
+        f64::NAN
+    }
+}
+
+/// Second derivatives of the natural cubic spline through the points, zero at both ends
+fn natural_spline(points: &[(f64, f64)]) -> Vec<f64> {
+    let n = points.len();
+    let mut second_derivatives = vec![0.0; n];
+    if n < 3 { return second_derivatives }
+
+    // Tridiagonal system for the inner points, solved with the Thomas algorithm
+    let h: Vec<_> = points.windows(2).map(|w| w[1].0 - w[0].0).collect();
+    let mut diagonal = vec![0.0; n];
+    let mut right = vec![0.0; n];
+    for i in 1..n - 1 {
+        diagonal[i] = 2.0 * (h[i - 1] + h[i]);
+        right[i] = 6.0 * ((points[i + 1].1 - points[i].1) / h[i] - (points[i].1 - points[i - 1].1) / h[i - 1]);
+    }
+    for i in 2..n - 1 {
+        let factor = h[i - 1] / diagonal[i - 1];
+        diagonal[i] -= factor * h[i - 1];
+        right[i] -= factor * right[i - 1];
+    }
+    for i in (1..n - 1).rev() {
+        second_derivatives[i] = (right[i] - h[i] * second_derivatives[i + 1]) / diagonal[i];
+    }
+    second_derivatives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regression(model: Model, standards: Vec<(f64, f64)>) -> Regression {
+        let weights = vec![1.0; standards.len()];
+        let mut regression = Regression { model, standards, weights, ..Default::default() };
+        regression.curve_fit();
+        regression
+    }
+
+    #[test]
+    fn log_log_recovers_a_power_law() {
+        let standards: Vec<_> = [1.0, 3.0, 10.0, 30.0, 100.0].iter().map(|&x: &f64| (x, 0.02 * x.powf(0.8))).collect();
+        let regression = regression(Model::LogLog, standards);
+        assert!((regression.coefficients[0] - 0.02f64.log10()).abs() < 1e-9);
+        assert!((regression.coefficients[1] - 0.8).abs() < 1e-9);
+        assert!((regression.inverse(regression.evaluate(42.0)) - 42.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn log_log_residuals_are_in_log_space() {
+        let standards = vec![(1.0, 0.11), (10.0, 0.9), (100.0, 8.5), (1000.0, 95.0)];
+        let regression = regression(Model::LogLog, standards.clone());
+        let expected: f64 = standards.iter().map(|&(x, y)| {
+            let diff = y.log10() - (regression.coefficients[0] + regression.coefficients[1] * x.log10());
+            diff * diff
+        }).sum();
+        assert!((regression.sum_of_squares() - expected).abs() < 1e-12);
+        assert!((regression.sy_x() - (expected / 2.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn quadratic_backfits_on_the_side_of_the_standards() {
+        let curve = |x: f64| 0.5 + 0.01 * x - 0.00002 * x * x;
+        let standards: Vec<_> = [10.0, 25.0, 50.0, 100.0, 150.0, 200.0].iter().map(|&x| (x, curve(x))).collect();
+        let regression = regression(Model::Quadratic, standards);
+        assert!((regression.inverse(curve(120.0)) - 120.0).abs() < 1e-6);
+    }
+
+    // The natural spline through points on a line is that line
+    #[test]
+    fn spline_through_a_line_is_the_line() {
+        let standards: Vec<_> = [1.0, 2.0, 5.0, 10.0, 50.0].iter().map(|&x: &f64| (x, 0.3 + 0.2 * x.ln())).collect();
+        let regression = regression(Model::CubicSpline, standards);
+        for x in [1.5, 7.0, 30.0, 100.0] {
+            assert!((regression.evaluate(x) - (0.3 + 0.2 * x.ln())).abs() < 1e-9);
+        }
+        assert!((regression.inverse(0.3 + 0.2 * 20f64.ln()) - 20.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn point_to_point_interpolates_in_log_space() {
+        let regression = regression(Model::PointToPoint, vec![(1.0, 0.0), (100.0, 1.0)]);
+        assert!((regression.evaluate(10.0) - 0.5).abs() < 1e-12);
+        assert!((regression.inverse(0.5) - 10.0).abs() < 1e-9);
+        assert!(regression.inverse(1.5).is_nan());
+    }
+
+    #[test]
+    fn standards_of_the_same_concentration_are_merged() {
+        let standards = vec![(1.0, 0.1), (10.0, 0.5), (10.0, 0.7), (100.0, 1.0), (1000.0, 1.2)];
+        for model in [Model::CubicSpline, Model::PointToPoint] {
+            let regression = regression(model, standards.clone());
+            assert!((regression.evaluate(10.0) - 0.6).abs() < 1e-12, "{model:?}");
+            assert!(regression.evaluate(30.0).is_finite(), "{model:?}");
+            assert!((regression.inverse(0.6) - 10.0).abs() < 1e-6, "{model:?}");
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::statistics::SplitMix64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MultiStart {
+    pub enabled: bool,
+    pub starts: usize,
+    pub spread: f64, // how far around the data range the seeds are scattered
+    pub seed: u64,
+}
+
+impl Default for MultiStart {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            starts: 20,
+            spread: 1.0,
+            seed: 1,
+        }
+    }
+}
+
+/// Everything needed to reproduce a multi-start search
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MultiStartReport {
+    pub starts: usize,
+    pub spread: f64,
+    pub seed: u64,
+    pub ranges: [(f64, f64); 4],          // range each of a, b, c, d was sampled from
+    pub seeds: Vec<(f64, f64, f64, f64)>, // initial a, b, c, d of every start
+    pub sse: Vec<f64>,                    // sum of squares every start converged to
+    pub best_start: Option<usize>,        // None if no start beat the heuristic initial guess
+}
+
+impl MultiStartReport {
+    pub fn description(&self) -> String {
+        let best = match self.best_start {
+            Some(start) => format!("start {}", start + 1),
+            None => String::from("initial guess"),
+        };
+        format!("Best of {} starts: {best} (seed {}, spread {})", self.starts + 1, self.seed, self.spread)
+    }
+}
+
+/// Latin hypercube sampling, every range is split into `samples` strata and each stratum is hit exactly once
+pub fn latin_hypercube<const N: usize>(ranges: [(f64, f64); N], samples: usize, seed: u64) -> Vec<[f64; N]> {
+    let mut rng = SplitMix64(seed);
+    let mut result = vec![[0.0; N]; samples];
+
+    for (dimension, (low, high)) in ranges.into_iter().enumerate() {
+        // Fisher-Yates shuffle of the strata
+        let mut strata: Vec<usize> = (0..samples).collect();
+        for i in (1..samples).rev() {
+            let j = rng.next_index(i + 1);
+            strata.swap(i, j);
+        }
+
+        for (sample, stratum) in result.iter_mut().zip(strata) {
+            let t = (stratum as f64 + rng.next_f64()) / samples as f64;
+            sample[dimension] = low + t * (high - low);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_sample_in_every_stratum() {
+        let ranges = [(0.0, 1.0), (-5.0, 5.0), (100.0, 200.0)];
+        let samples = latin_hypercube(ranges, 10, 42);
+        assert_eq!(samples.len(), 10);
+        for (dimension, (low, high)) in ranges.into_iter().enumerate() {
+            let mut strata: Vec<_> = samples.iter()
+                .map(|sample| ((sample[dimension] - low) / (high - low) * 10.0).floor() as usize)
+                .collect();
+            strata.sort();
+            assert_eq!(strata, (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn same_seed_same_samples() {
+        let ranges = [(0.0, 1.0), (1.0, 2.0)];
+        assert_eq!(latin_hypercube(ranges, 20, 7), latin_hypercube(ranges, 20, 7));
+        assert_ne!(latin_hypercube(ranges, 20, 7), latin_hypercube(ranges, 20, 8));
+    }
+}
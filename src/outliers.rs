@@ -0,0 +1,192 @@
+use crate::{logistic_regression::*, statistics::*};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutlierTest {
+    #[default]
+    Grubbs, // The most extreme replicate of a group, for 3 or more replicates
+    DixonQ, // Gap to the nearest replicate over the range, for 3 to 10 replicates
+    Rout,   // Fit residuals of the standard wells, with a false discovery rate of 1%
+}
+
+impl OutlierTest {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutlierTest::Grubbs => "Grubbs",
+            OutlierTest::DixonQ => "Dixon Q",
+            OutlierTest::Rout => "ROUT",
+        }
+    }
+}
+
+/// A well the test would exclude, nothing changes until the user accepts it
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub well: usize,
+    pub reason: String,
+}
+
+const ALPHA: f64 = 0.05;
+const ROUT_Q: f64 = 0.01;
+// Critical Q at 95% confidence for 3 to 10 replicates
+const DIXON_CRITICAL: [f64; 8] = [0.970, 0.829, 0.710, 0.625, 0.568, 0.526, 0.493, 0.466];
+
+/// Runs the test on the wells that are not excluded yet. ROUT needs a curve, so it fails if the plate cannot be fitted.
+pub fn suggest_outliers(microplate: &Microplate, test: OutlierTest) -> Result<Vec<Suggestion>, ValueError> {
+    if test == OutlierTest::Rout { return rout(microplate) }
+
+    // Wells of every replicate group, blanks and controls each form a single group
+    let mut groups: Vec<((SampleType, usize), Vec<usize>)> = Vec::new();
+    for (well, sample) in microplate.samples.iter().enumerate() {
+        if sample.typ == SampleType::Unused || sample.excluded.is_some() || sample.value.is_none() { continue }
+        let key = match sample.typ {
+            SampleType::Standard | SampleType::Unknown | SampleType::QualityControl => (sample.typ, sample.group),
+            typ => (typ, 0),
+        };
+        match groups.iter_mut().find(|(group_key, _)| *group_key == key) {
+            Some((_, wells)) => wells.push(well),
+            None => groups.push((key, vec![well])),
+        }
+    }
+
+    let mut suggestions = Vec::new();
+    for (_, wells) in groups {
+        let mut values: Vec<_> = wells.iter().map(|&well| (well, microplate.samples[well].value.unwrap())).collect();
+        values.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        let suggestion = match test {
+            OutlierTest::Grubbs => grubbs(&values),
+            OutlierTest::DixonQ => dixon(&values),
+            OutlierTest::Rout => None,
+        };
+        suggestions.extend(suggestion);
+    }
+    Ok(suggestions)
+}
+
+// Two-sided, on values sorted ascending
+fn grubbs(values: &[(usize, f64)]) -> Option<Suggestion> {
+    let n = values.len();
+    if n < 3 { return None }
+    let (mean, sd) = mean_and_sd(&values.iter().map(|&(_, value)| value).collect::<Vec<_>>());
+    if sd <= 0.0 { return None }
+
+    let (well, value) = [values[0], values[n - 1]].into_iter()
+        .max_by(|(_, a), (_, b)| (a - mean).abs().total_cmp(&(b - mean).abs()))?;
+    let g = (value - mean).abs() / sd;
+
+    let n = n as f64;
+    let t = t_quantile(1.0 - ALPHA / (2.0 * n), n - 2.0);
+    let critical = (n - 1.0) / n.sqrt() * (t * t / (n - 2.0 + t * t)).sqrt();
+    (g > critical).then(|| Suggestion { well, reason: format!("Grubbs, G = {g:.3} > {critical:.3}") })
+}
+
+fn dixon(values: &[(usize, f64)]) -> Option<Suggestion> {
+    let n = values.len();
+    if !(3..=10).contains(&n) { return None }
+    let range = values[n - 1].1 - values[0].1;
+    if range <= 0.0 { return None }
+
+    let low = ((values[1].1 - values[0].1) / range, values[0].0);
+    let high = ((values[n - 1].1 - values[n - 2].1) / range, values[n - 1].0);
+    let (q, well) = if low.0 > high.0 { low } else { high };
+    let critical = DIXON_CRITICAL[n - 3];
+    (q > critical).then(|| Suggestion { well, reason: format!("Dixon, Q = {q:.3} > {critical:.3}") })
+}
+
+// Residuals scaled by the robust standard deviation, tested with the Benjamini-Hochberg false discovery rate
+fn rout(microplate: &Microplate) -> Result<Vec<Suggestion>, ValueError> {
+    let regression = Regression::new(microplate)?;
+
+    let wells: Vec<_> = microplate.samples.iter().enumerate()
+        .filter(|(_, sample)| sample.typ == SampleType::Standard && sample.excluded.is_none())
+        .filter_map(|(well, sample)| {
+            let x = microplate.standard_groups[sample.group].concentration?;
+            Some((well, x, sample.value? - regression.blank))
+        })
+        .collect();
+    // Weighted by the curve, a single well is too noisy
+    let points: Vec<_> = wells.iter().map(|&(_, x, _y)| (x, regression.evaluate(x))).collect();
+    let weights = regression.options.weighting.weights(&points, regression.variance_power);
+    let residuals: Vec<_> = wells.iter().zip(&weights)
+        .map(|(&(_, x, y), weight)| (y - regression.evaluate(x)) * weight.sqrt())
+        .collect();
+
+    let n = residuals.len() as f64;
+    let degrees_of_freedom = n - regression.parameter_count() as f64;
+    if degrees_of_freedom < 1.0 { return Ok(Vec::new()) }
+
+    // Robust standard deviation of the residuals, the 68.27th percentile of their absolute values
+    let mut absolute: Vec<_> = residuals.iter().map(|r| r.abs()).collect();
+    absolute.sort_by(f64::total_cmp);
+    let robust_sd = percentile(&absolute, 0.6827) * n / degrees_of_freedom;
+    if robust_sd <= 0.0 { return Ok(Vec::new()) }
+
+    let mut p_values: Vec<_> = residuals.iter().enumerate()
+        .map(|(i, r)| (i, 2.0 * (1.0 - t_cdf(r.abs() / robust_sd, degrees_of_freedom))))
+        .collect();
+    p_values.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    let rejected = p_values.iter().enumerate()
+        .rposition(|(rank, &(_, p))| p <= (rank + 1) as f64 / n * ROUT_Q)
+        .map_or(0, |rank| rank + 1);
+
+    Ok(p_values[..rejected].iter().map(|&(i, p)| Suggestion {
+        well: wells[i].0,
+        reason: format!("ROUT (Q = 1%), residual {:.4}, p = {p:.2e}", residuals[i]),
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wells numbered in the order of the values, sorted ascending like the tests expect them
+    fn sorted(values: &[f64]) -> Vec<(usize, f64)> {
+        let mut values: Vec<_> = values.iter().copied().enumerate().collect();
+        values.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        values
+    }
+
+    #[test]
+    fn grubbs_flags_the_extreme_value() {
+        let suggestion = grubbs(&sorted(&[199.31, 199.53, 200.19, 200.82, 201.92, 201.95, 202.18, 245.57])).unwrap();
+        assert_eq!(suggestion.well, 7);
+        // Two-sided critical value for 8 values at alpha = 0.05
+        assert!(suggestion.reason.ends_with("> 2.127"), "{}", suggestion.reason);
+    }
+
+    #[test]
+    fn grubbs_keeps_values_within_the_critical_value() {
+        assert!(grubbs(&sorted(&[9.8, 10.0, 10.1, 10.2, 10.4, 10.6])).is_none());
+        assert!(grubbs(&sorted(&[1.0, 5.0])).is_none());
+        assert!(grubbs(&sorted(&[3.0, 3.0, 3.0])).is_none());
+    }
+
+    #[test]
+    fn dixon_flags_a_gap_above_the_critical_q() {
+        let suggestion = dixon(&sorted(&[10.2, 10.3, 11.6, 10.4])).unwrap();
+        assert_eq!(suggestion.well, 2);
+        assert!(suggestion.reason.starts_with("Dixon, Q = 0.857 > 0.829"), "{}", suggestion.reason);
+    }
+
+    // Q = 0.455 for the lowest value, just below the critical 0.466 of 10 values
+    #[test]
+    fn dixon_keeps_a_gap_below_the_critical_q() {
+        let values = [0.189, 0.167, 0.187, 0.183, 0.186, 0.182, 0.181, 0.184, 0.181, 0.177];
+        assert!(dixon(&sorted(&values)).is_none());
+        assert!(dixon(&sorted(&[1.0; 11])).is_none());
+    }
+
+    #[test]
+    fn blanks_form_one_group() {
+        let mut microplate = Microplate::new(3, 2);
+        for (sample, value) in microplate.samples.iter_mut().zip([0.05, 0.06, 0.05, 0.06, 0.055, 0.4]) {
+            sample.typ = SampleType::Blank;
+            sample.value = Some(value);
+        }
+        let suggestions = suggest_outliers(&microplate, OutlierTest::Grubbs).unwrap();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].well, 5);
+
+        microplate.samples[5].excluded = Some(String::from("Grubbs"));
+        assert!(suggest_outliers(&microplate, OutlierTest::Grubbs).unwrap().is_empty());
+    }
+}
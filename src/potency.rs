@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{levenberg_marquardt::*, logistic_regression::*, statistics::*};
+
+/// How a test sample is judged parallel to the reference
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ParallelismTest {
+    FTest { alpha: f64 },                   // parallel unless the shared fit is significantly worse than two separate ones
+    Equivalence { lower: f64, upper: f64 }, // 90% CIs of the slope and upper asymptote ratios within the margins
+}
+
+impl Default for ParallelismTest {
+    fn default() -> Self {
+        ParallelismTest::FTest { alpha: 0.05 }
+    }
+}
+
+impl ParallelismTest {
+    pub fn name(&self) -> String {
+        match self {
+            ParallelismTest::FTest { alpha } => format!("F-test, alpha {alpha}"),
+            ParallelismTest::Equivalence { lower, upper } => format!("Equivalence, ratios within {lower} - {upper}"),
+        }
+    }
+}
+
+/// Test sample over reference of a parameter of the separate fits
+#[derive(Clone, Debug)]
+pub struct Ratio {
+    pub name: &'static str,
+    pub value: f64,
+    pub confidence_interval: (f64, f64), // 90%, as for two one-sided tests at 5%
+}
+
+/// A dilution series of unknowns compared to the standards. The unknown groups of one test sample share
+/// a label, and their concentration is the dose the sample would have if it was as potent as the reference.
+#[derive(Clone, Debug)]
+pub struct Potency {
+    pub label: String,
+    pub points: Vec<(f64, f64)>, // dose and corrected response of the test sample
+    pub potency: f64,            // c of the reference over c of the test sample, with shared a, b, d (and g)
+    pub confidence_interval: (f64, f64), // 95%
+    pub f: f64,
+    pub p_value: f64, // of the shared fit against separate fits, small values mean the curves are not parallel
+    pub ratios: Vec<Ratio>,
+    pub parallel: bool,
+    pub convergence: Convergence, // of the shared fit
+    pub curve: Vec<f64>,          // a, b, ln(c), d (and g) of the test sample in the shared fit
+}
+
+// Fit of one or more curves over ln(x)
+struct Fit {
+    parameters: Vec<f64>,
+    sse: f64,
+    covariance: Vec<Vec<f64>>,
+    convergence: Convergence,
+}
+
+impl Regression {
+    /// Compares every labelled series of unknowns with a concentration to the standards, logistic models only
+    pub fn relative_potencies(&self, microplate: &Microplate) -> Vec<Potency> {
+        if !self.model.is_logistic() { return Vec::new() }
+
+        let mut series: Vec<(String, Vec<(f64, f64)>)> = Vec::new();
+        for (i, group) in microplate.unknown_groups.iter().enumerate() {
+            let Some(dose) = group.concentration else { continue };
+            if group.label.is_empty() || dose.is_nan() || dose <= 0.0 { continue }
+
+            let values: Vec<_> = microplate.samples.iter()
+                .filter(|sample| sample.typ == SampleType::Unknown && sample.group == i && sample.excluded.is_none())
+                .filter_map(|sample| sample.value)
+                .map(|value| value - self.blank)
+                .collect();
+            if values.is_empty() { continue }
+            let points: Vec<_> = if self.options.fit_replicates {
+                values.iter().map(|&value| (dose, value)).collect()
+            } else {
+                vec![(dose, mean_and_sd(&values).0)]
+            };
+
+            match series.iter_mut().find(|(label, _)| *label == group.label) {
+                Some((_, series_points)) => series_points.extend(points),
+                None => series.push((group.label.clone(), points)),
+            }
+        }
+
+        series.into_iter().filter_map(|(label, points)| self.relative_potency(label, points)).collect()
+    }
+
+    fn relative_potency(&self, label: String, mut points: Vec<(f64, f64)>) -> Option<Potency> {
+        points.sort_by(|(a_x, _), (b_x, _)| a_x.total_cmp(b_x));
+        let k = self.model.parameter_count();
+        let reference = self.fit_points();
+        let n = reference.len() + points.len();
+        // Both curves need their own residual degrees of freedom
+        if points.len() < k || n <= 2 * k { return None }
+
+        let all: Vec<_> = reference.iter().chain(&points).copied().collect();
+        let weights = self.options.weighting.weights(&all, self.variance_power);
+        let (reference_weights, test_weights) = weights.split_at(reference.len());
+        let reference: Vec<_> = reference.iter().map(|&(x, y)| (x.ln(), y)).collect();
+        let test: Vec<_> = points.iter().map(|&(x, y)| (x.ln(), y)).collect();
+
+        // Separate fits, the test sample starts from the reference moved to where it crosses the middle response
+        let curve = |x: f64, p: &[f64], gradient: &mut [f64]| {
+            log_logistic(x, p[0], p[1], p[2], p[3], p.get(4).copied().unwrap_or(1.0), gradient)
+        };
+        let reference_fit = fit(self.solver_parameters(), &reference, reference_weights, k == 5, curve);
+        let mut initial = reference_fit.parameters.clone();
+        initial[2] = middle_crossing(&test, (initial[0] + initial[3]) / 2.0);
+        let test_fit = fit(initial, &test, test_weights, k == 5, curve);
+
+        // Shared fit of a, b, ln(c) of the reference, d, (g,) ln(c) of the test sample.
+        // The solver only passes x, so x is the index of the point and the curve looks it up.
+        let doses: Vec<_> = reference.iter().map(|&(x, _y)| (x, false)).chain(test.iter().map(|&(x, _y)| (x, true))).collect();
+        let indexed: Vec<_> = reference.iter().chain(&test).enumerate().map(|(i, &(_x, y))| (i as f64, y)).collect();
+        let shared_curve = |i: f64, p: &[f64], gradient: &mut [f64]| {
+            let (x, is_test) = doses[i as usize];
+            let c = if is_test { p[k] } else { p[2] };
+            let g = if k == 5 { p[4] } else { 1.0 };
+            let value = log_logistic(x, p[0], p[1], c, p[3], g, &mut gradient[..k]);
+            gradient[k] = 0.0;
+            if is_test { gradient.swap(2, k) }
+            value
+        };
+        let mut initial = reference_fit.parameters.clone();
+        initial.push(test_fit.parameters[2]);
+        let shared_fit = fit(initial, &indexed, &weights, k == 5, shared_curve);
+
+        let separate_sse = reference_fit.sse + test_fit.sse;
+        let separate_df = (n - 2 * k) as f64;
+        let shared_df = (n - k - 1) as f64;
+        let f = (((shared_fit.sse - separate_sse) / (k - 1) as f64) / (separate_sse / separate_df)).max(0.0);
+        let p_value = 1.0 - f_cdf(f, (k - 1) as f64, separate_df);
+
+        let parameters = &shared_fit.parameters;
+        let covariance = &shared_fit.covariance;
+        let log_potency = parameters[2] - parameters[k];
+        let se = (covariance[2][2] + covariance[k][k] - 2.0 * covariance[2][k]).sqrt();
+        let t = t_quantile(0.975, shared_df);
+        let confidence_interval = ((log_potency - t * se).exp(), (log_potency + t * se).exp());
+
+        // The upper asymptote is the 0-dose end of a decreasing curve
+        let upper = if self.direction == Direction::Decreasing { 0 } else { 3 };
+        let t = t_quantile(0.95, separate_df);
+        let ratios: Vec<_> = [("Slope", 1), ("Upper asymptote", upper)].into_iter().map(|(name, i)| {
+            let (test_value, reference_value) = (test_fit.parameters[i], reference_fit.parameters[i]);
+            let value = test_value / reference_value;
+            let se = (test_fit.covariance[i][i] / (test_value * test_value)
+                + reference_fit.covariance[i][i] / (reference_value * reference_value)).sqrt();
+            Ratio { name, value, confidence_interval: (value * (-t * se).exp(), value * (t * se).exp()) }
+        }).collect();
+
+        let parallel = match self.options.parallelism {
+            ParallelismTest::FTest { alpha } => p_value > alpha,
+            ParallelismTest::Equivalence { lower, upper } => ratios.iter().all(|Ratio { confidence_interval: (low, high), .. }| {
+                *low >= lower && *high <= upper
+            }),
+        };
+
+        let mut curve = parameters[..k].to_vec();
+        curve[2] = parameters[k];
+        Some(Potency {
+            label,
+            points,
+            potency: log_potency.exp(),
+            confidence_interval,
+            f,
+            p_value,
+            ratios,
+            parallel,
+            convergence: shared_fit.convergence,
+            curve,
+        })
+    }
+}
+
+// The asymmetry g of 5PL is always the fifth parameter
+fn fit(mut parameters: Vec<f64>, points: &[(f64, f64)], weights: &[f64], five_pl: bool, curve: impl Fn(f64, &[f64], &mut [f64]) -> f64) -> Fit {
+    let convergence = LevenbergMarquardt::default().minimize(
+        &mut parameters,
+        points,
+        weights,
+        &curve,
+        // The asymmetry has to stay positive, otherwise the curve flips
+        |p| if five_pl { p[4] = p[4].clamp(0.05, 20.0) },
+    );
+    let sse: f64 = points.iter().zip(weights).map(|(&(x, y), weight)| {
+        let diff = y - curve(x, &parameters, &mut vec![0.0; parameters.len()]);
+        weight * diff * diff
+    }).sum();
+
+    let n = parameters.len();
+    let variance = sse / (points.len() - n) as f64;
+    let covariance = invert(&information_matrix(&parameters, points, weights, &curve))
+        .map(|inverse| inverse.into_iter().map(|row| row.into_iter().map(|v| v * variance).collect()).collect())
+        .unwrap_or_else(|| vec![vec![f64::NAN; n]; n]);
+
+    Fit { parameters, sse, covariance, convergence }
+}
+
+// ln(x) where the straight lines between the points first reach the response, or their middle
+fn middle_crossing(points: &[(f64, f64)], response: f64) -> f64 {
+    points.windows(2)
+        .find(|window| (window[0].1 - response) * (window[1].1 - response) <= 0.0 && window[0].1 != window[1].1)
+        .map(|window| {
+            let ((x0, y0), (x1, y1)) = (window[0], window[1]);
+            x0 + (x1 - x0) * (response - y0) / (y1 - y0)
+        })
+        .unwrap_or_else(|| points.iter().map(|&(x, _y)| x).sum::<f64>() / points.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Standards on a 4PL curve, and a test sample whose doses respond like `test` of them
+    fn microplate(test: impl Fn(f64) -> f64) -> Microplate {
+        let curve = |x: f64| 0.1 + (2.5 - 0.1) / (1.0 + (x / 150.0).powf(-1.3));
+        let noise = [0.01, -0.012, 0.006, -0.004, 0.0, 0.011, -0.008, 0.005];
+        let mut microplate = Microplate::new(12, 8);
+        let mut well = 0;
+
+        let standards = [1000.0, 500.0, 250.0, 125.0, 62.5, 31.25, 15.6, 7.8];
+        microplate.standard_groups = standards.iter().map(|&x| Group { concentration: Some(x), ..Default::default() }).collect();
+        for (group, &x) in standards.iter().enumerate() {
+            for replicate in 0..2 {
+                let sample = &mut microplate.samples[well];
+                (sample.typ, sample.group) = (SampleType::Standard, group);
+                sample.value = Some(curve(x) * (1.0 + noise[(group + replicate) % noise.len()]));
+                well += 1;
+            }
+        }
+
+        let doses = [800.0, 400.0, 200.0, 100.0, 50.0, 25.0];
+        microplate.unknown_groups = doses.iter().map(|&x| Group { concentration: Some(x), label: String::from("Sample"), ..Default::default() }).collect();
+        for (group, &x) in doses.iter().enumerate() {
+            let sample = &mut microplate.samples[well];
+            (sample.typ, sample.group) = (SampleType::Unknown, group);
+            sample.value = Some(curve(test(x)) * (1.0 + noise[(group + 3) % noise.len()]));
+            well += 1;
+        }
+        microplate
+    }
+
+    #[test]
+    fn twice_as_potent_sample() {
+        let regression = Regression::new(&microplate(|x| 2.0 * x)).unwrap();
+        let [potency] = regression.potencies.as_slice() else { panic!("{:?}", regression.potencies) };
+        assert_eq!(potency.label, "Sample");
+        assert!((potency.potency - 2.0).abs() < 0.1, "{}", potency.potency);
+        let (low, high) = potency.confidence_interval;
+        assert!(low < potency.potency && potency.potency < high);
+        assert!(potency.parallel, "p = {}", potency.p_value);
+    }
+
+    #[test]
+    fn steeper_sample_is_not_parallel() {
+        let regression = Regression::new(&microplate(|x| 150.0 * (x / 150.0).powf(2.0))).unwrap();
+        let [potency] = regression.potencies.as_slice() else { panic!("{:?}", regression.potencies) };
+        assert!(!potency.parallel, "p = {}", potency.p_value);
+        let slope = potency.ratios.iter().find(|ratio| ratio.name == "Slope").unwrap();
+        assert!((slope.value - 2.0).abs() < 0.2, "{}", slope.value);
+    }
+
+    #[test]
+    fn only_logistic_models_have_potencies() {
+        let mut microplate = microplate(|x| 2.0 * x);
+        microplate.model = Model::CubicSpline;
+        assert!(Regression::new(&microplate).unwrap().potencies.is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{*, logistic_regression::*};
+
+/// Microplates of one study, saved together. The metadata belongs to the whole project.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    pub name: String,
+    pub description: String,
+    pub operator: String,
+    pub date: String,
+    pub plates: Vec<Microplate>, // never empty
+    #[serde(default)]
+    pub pooled: bool, // one curve through the standards of every plate
+}
+
+impl Project {
+    pub fn new(microplate: Microplate) -> Self {
+        Self {
+            plates: vec![microplate],
+            ..default()
+        }
+    }
+
+    /// Reads a project, or a single microplate saved before there were projects
+    pub fn from_json(bytes: &[u8]) -> Option<Self> {
+        match serde_json::from_slice::<Project>(bytes) {
+            Ok(project) if !project.plates.is_empty() => Some(project),
+            Ok(_) => None,
+            Err(_) => serde_json::from_slice::<Microplate>(bytes).ok().map(Project::new),
+        }
+    }
+
+    /// The plate with the standard wells of every other plate added. Their responses are moved by the
+    /// difference of the blanks, so the blank of this plate can be subtracted from all of them.
+    /// Without blanks on both plates there is no difference to move them by.
+    pub fn pooled_plate(&self, index: usize) -> Microplate {
+        let mut microplate = self.plates[index].clone();
+        let blank = mean_blank(&microplate);
+        // A plate without standards of its own only has the empty group of a new plate
+        if !microplate.samples.iter().any(|sample| sample.typ == SampleType::Standard) {
+            microplate.standard_groups.clear();
+        }
+
+        for (i, plate) in self.plates.iter().enumerate() {
+            if i == index { continue }
+            let offset = blank.zip(mean_blank(plate)).map_or(0.0, |(blank, other)| blank - other);
+            for sample in &plate.samples {
+                if sample.typ != SampleType::Standard || sample.excluded.is_some() { continue }
+                let Group { concentration, unit, .. } = &plate.standard_groups[sample.group];
+                let unit = unit.unwrap_or(plate.unit);
+
+                // Standards of the same concentration form one group across the plates
+                let group = microplate.standard_groups.iter()
+                    .position(|group| group.concentration == *concentration && group.unit.unwrap_or(microplate.unit) == unit)
+                    .unwrap_or_else(|| {
+                        let unit = (unit != microplate.unit).then_some(unit);
+                        microplate.standard_groups.push(Group { concentration: *concentration, unit, ..default() });
+                        microplate.standard_groups.len() - 1
+                    });
+                microplate.samples.push(Sample {
+                    group,
+                    value: sample.value.map(|value| value + offset),
+                    ..sample.clone()
+                });
+            }
+        }
+        microplate
+    }
+
+    /// Fits every plate on its own, or every plate to the pooled standards
+    pub fn regressions(&self) -> Vec<Result<Regression, ValueError>> {
+        (0..self.plates.len()).map(|i| {
+            if self.pooled && self.plates.len() > 1 {
+                Regression::new(&self.pooled_plate(i))
+            } else {
+                Regression::new(&self.plates[i])
+            }
+        }).collect()
+    }
+
+    /// Name of the plate, or its number if it has none
+    pub fn plate_name(&self, index: usize) -> String {
+        match self.plates[index].name.as_str() {
+            "" => format!("Plate {}", index + 1),
+            name => name.to_owned(),
+        }
+    }
+}
+
+// Mean of the blank wells that are measured and not excluded, None without any
+fn mean_blank(microplate: &Microplate) -> Option<f64> {
+    let blanks: Vec<_> = microplate.samples.iter()
+        .filter(|sample| sample.typ == SampleType::Blank && sample.excluded.is_none())
+        .filter_map(|sample| sample.value)
+        .collect();
+    (!blanks.is_empty()).then(|| mean_and_sd(&blanks).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Unit;
+
+    // Standards of the concentrations in duplicate, and two blanks
+    fn plate(concentrations: &[f64], response: f64, blank: Option<f64>) -> Microplate {
+        let mut microplate = Microplate::new(12, 8);
+        microplate.unit = Unit::NanogramPerMl;
+        microplate.standard_groups = concentrations.iter().map(|&x| Group { concentration: Some(x), ..default() }).collect();
+        for (group, &x) in concentrations.iter().enumerate() {
+            for replicate in 0..2 {
+                let sample = &mut microplate.samples[group * 2 + replicate];
+                (sample.typ, sample.group, sample.value) = (SampleType::Standard, group, Some(x / 1000.0 + response));
+            }
+        }
+        for sample in &mut microplate.samples[20..22] {
+            (sample.typ, sample.value) = (SampleType::Blank, blank);
+        }
+        microplate
+    }
+
+    // Responses of the standard wells in every group
+    fn groups(microplate: &Microplate) -> Vec<(Option<f64>, Vec<f64>)> {
+        microplate.standard_groups.iter().enumerate().map(|(i, group)| {
+            let values = microplate.samples.iter()
+                .filter(|sample| sample.typ == SampleType::Standard && sample.group == i)
+                .filter_map(|sample| sample.value)
+                .collect();
+            (group.concentration, values)
+        }).collect()
+    }
+
+    #[test]
+    fn pools_standards_of_the_same_concentration() {
+        let mut other = plate(&[500.0, 250.0, 125.0], 0.2, Some(0.3));
+        other.samples[1].excluded = Some(String::from("Bubble"));
+        let project = Project { plates: vec![plate(&[1000.0, 500.0, 250.0], 0.0, Some(0.1)), other], ..default() };
+
+        // The other plate is 0.2 higher, its blank too
+        let pooled = groups(&project.pooled_plate(0));
+        let close = |a: &[f64], b: &[f64]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-12);
+        assert_eq!(pooled.iter().map(|(x, _)| *x).collect::<Vec<_>>(), [Some(1000.0), Some(500.0), Some(250.0), Some(125.0)]);
+        assert!(close(&pooled[0].1, &[1.0, 1.0]));
+        assert!(close(&pooled[1].1, &[0.5, 0.5, 0.5]), "{:?}", pooled[1].1);
+        assert!(close(&pooled[2].1, &[0.25, 0.25, 0.25, 0.25]));
+        assert!(close(&pooled[3].1, &[0.125, 0.125]));
+
+        // Seen from the other plate, the first one is moved up
+        let pooled = groups(&project.pooled_plate(1));
+        assert!(close(&pooled[3].1, &[1.2, 1.2]), "{:?}", pooled);
+    }
+
+    #[test]
+    fn standards_in_another_unit_get_their_own_group() {
+        let mut other = plate(&[500.0], 0.0, Some(0.1));
+        other.standard_groups[0].unit = Some(Unit::PicogramPerMl);
+        let project = Project { plates: vec![plate(&[500.0], 0.0, Some(0.1)), other], ..default() };
+        let pooled = project.pooled_plate(0);
+        assert_eq!(pooled.standard_groups.len(), 2);
+        assert_eq!(pooled.standard_groups[1].unit, Some(Unit::PicogramPerMl));
+    }
+
+    #[test]
+    fn no_blank_offset_without_blanks() {
+        for (first, second) in [(None, Some(0.3)), (Some(0.1), None)] {
+            let project = Project { plates: vec![plate(&[1000.0], 0.0, first), plate(&[500.0], 0.2, second)], ..default() };
+            let pooled = groups(&project.pooled_plate(0));
+            assert!(pooled[1].1.iter().all(|&value| (value - 0.7).abs() < 1e-12), "{pooled:?}");
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use calamine::{Data, DataType, Range};
+use serde::{Deserialize, Serialize};
+
+use crate::import::*;
+
+/// Plate reader software whose exports are recognized when they are opened
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReaderFormat {
+    Gen5,
+    Tecan,
+    SoftMaxPro,
+    Bmg,
+}
+
+impl ReaderFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gen5 => "BioTek Gen5",
+            Self::Tecan => "Tecan i-control/Magellan",
+            Self::SoftMaxPro => "SoftMax Pro",
+            Self::Bmg => "BMG Labtech",
+        }
+    }
+
+    /// Recognizes an export by the names the software writes into it
+    pub fn detect(sheet: &Range<Data>) -> Option<Self> {
+        let texts: Vec<String> = cells(sheet).map(|cell| cell_text(sheet, cell).to_lowercase()).filter(|text| !text.is_empty()).collect();
+        let any = |patterns: &[&str]| texts.iter().any(|text| patterns.iter().any(|pattern| text.contains(pattern)));
+
+        if texts.iter().any(|text| text.starts_with("##blocks")) {
+            Some(Self::SoftMaxPro)
+        } else if any(&["gen5", "experiment file path", "protocol file path"]) {
+            Some(Self::Gen5)
+        } else if any(&["tecan", "i-control", "magellan"]) {
+            Some(Self::Tecan)
+        } else if any(&["bmg", "clariostar", "fluostar", "pherastar", "spectrostar", "polarstar", "nephelostar"]) {
+            Some(Self::Bmg)
+        } else {
+            None
+        }
+    }
+}
+
+/// Details of the read, taken from the export the measurements came from
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReadInfo {
+    pub format: Option<ReaderFormat>,
+    pub wavelength: Option<f64>,  // nm
+    pub read_time: Option<String>, // as written by the reader
+    pub serial: Option<String>,
+    pub temperature: Option<f64>, // °C
+}
+
+impl ReadInfo {
+    /// Label and value of every known detail
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(format) = self.format { fields.push(("Reader", format.name().to_owned())) }
+        if let Some(serial) = &self.serial { fields.push(("Serial", serial.clone())) }
+        if let Some(wavelength) = self.wavelength { fields.push(("Wavelength", format!("{wavelength} nm"))) }
+        if let Some(temperature) = self.temperature { fields.push(("Temperature", format!("{temperature} °C"))) }
+        if let Some(read_time) = &self.read_time { fields.push(("Read", read_time.clone())) }
+        fields
+    }
+}
+
+/// Measurements and details of an export of a known reader, None if the format is not recognized
+pub fn read_export(sheet: &Range<Data>) -> Option<(CellRange, ReadInfo)> {
+    use ReaderFormat::*;
+    let format = ReaderFormat::detect(sheet)?;
+    let (serial, date, time, temperature): (&[&str], &[&str], &[&str], &[&str]) = match format {
+        SoftMaxPro => return softmax_export(sheet),
+        Gen5 => (&["reader serial number", "serial number"], &["date"], &["time"], &["actual temperature", "read temperature", "temperature"]),
+        Tecan => (&["serial number", "serial no."], &["start time", "date"], &[], &["temperature"]),
+        Bmg => (&["reader serial number", "serial number", "serial no."], &["date"], &["time"], &["temperature", "target temperature"]),
+    };
+    let range = detect_plate(sheet)?;
+    let read_time = [field(sheet, date), field(sheet, time)].into_iter().flatten().collect::<Vec<_>>().join(" ");
+
+    let info = ReadInfo {
+        format: Some(format),
+        wavelength: wavelength(sheet),
+        read_time: (!read_time.is_empty()).then_some(read_time),
+        serial: field(sheet, serial),
+        temperature: field(sheet, temperature).and_then(|text| numbers(&text).next()),
+    };
+    Some((range, info))
+}
+
+// SoftMax Pro writes the plate without row letters, below a header row that starts with the temperature.
+// The first row of measurements holds the temperature of the read in front of the plate.
+fn softmax_export(sheet: &Range<Data>) -> Option<(CellRange, ReadInfo)> {
+    let (row, column) = cells(sheet).find(|&cell| cell_text(sheet, cell).starts_with("Temperature"))?;
+    let width = (0..).take_while(|&i| cell_text(sheet, (row, column + 1 + i)) == (i + 1).to_string()).count();
+    let number = |cell: (usize, usize)| sheet.get_value((cell.0 as u32, cell.1 as u32)).and_then(DataType::as_f64);
+    let height = (0..).take_while(|&i| number((row + 1 + i, column + 1)).is_some()).count();
+    if width < 2 || height < 2 { return None }
+
+    // The settings of the block, like "Plate: Plate1 1.3 PlateFormat Endpoint Absorbance Raw FALSE 1 ... 450 1 12 96 1 8"
+    let wavelength = cells(sheet)
+        .find(|&cell| cell_text(sheet, cell) == "Plate:")
+        .and_then(|(plate_row, _)| {
+            let (_, end) = sheet.end()?;
+            (0..=end as usize).filter_map(|column| cell_text(sheet, (plate_row, column)).parse::<f64>().ok()).find(is_wavelength)
+        });
+
+    let info = ReadInfo {
+        format: Some(ReaderFormat::SoftMaxPro),
+        wavelength,
+        read_time: field(sheet, &["date last saved"]),
+        serial: None,
+        temperature: number((row + 1, column)),
+    };
+    Some((CellRange::from_corner((row + 1, column + 1), width, height), info))
+}
+
+fn cells(sheet: &Range<Data>) -> impl Iterator<Item = (usize, usize)> {
+    let ((start_row, start_column), (end_row, end_column)) = sheet.start().zip(sheet.end()).unwrap_or(((1, 1), (0, 0)));
+    (start_row as usize..=end_row as usize).flat_map(move |row| (start_column as usize..=end_column as usize).map(move |column| (row, column)))
+}
+
+// Value of a label like "Serial number:", in the same cell after the colon or in the next filled cell of the row.
+// Some readers put several labels into one cell, separated by semicolons.
+fn field(sheet: &Range<Data>, keys: &[&str]) -> Option<String> {
+    for (row, column) in cells(sheet) {
+        let text = cell_text(sheet, (row, column));
+        for part in text.split(';') {
+            let (label, value) = part.split_once(':').unwrap_or((part, ""));
+            if !keys.iter().any(|key| label.trim().eq_ignore_ascii_case(key)) { continue }
+
+            let value = value.trim();
+            if !value.is_empty() { return Some(value.to_owned()) }
+            let (_, end) = sheet.end()?;
+            if let Some(value) = (column + 1..=end as usize).map(|column| cell_text(sheet, (row, column))).find(|value| !value.is_empty()) {
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+// From a wavelength label, or from a heading like "Raw Data (450)" or "450 nm" over the measurements
+fn wavelength(sheet: &Range<Data>) -> Option<f64> {
+    if let Some(wavelength) = field(sheet, &["wavelength", "wavelengths", "measurement wavelength"]).and_then(|text| numbers(&text).find(is_wavelength)) {
+        return Some(wavelength);
+    }
+    cells(sheet)
+        .map(|cell| cell_text(sheet, cell).to_lowercase())
+        .filter(|text| text.contains("nm") || text.contains('('))
+        .find_map(|text| numbers(&text).find(is_wavelength))
+}
+
+fn is_wavelength(value: &f64) -> bool {
+    (200.0..=1100.0).contains(value)
+}
+
+// Numbers within a text, with decimal points or commas
+fn numbers(text: &str) -> impl Iterator<Item = f64> + '_ {
+    text.split(|char: char| !char.is_ascii_digit() && char != '.' && char != ',')
+        .filter_map(|number| number.replace(',', ".").parse::<f64>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLATE: &str = "\t1\t2\t3\nA\t0.1\t0.2\t0.3\nB\t0.4\t0.5\t0.6\n";
+
+    fn export(header: &str) -> Range<Data> {
+        text_sheet(format!("{header}\n{PLATE}").as_bytes())
+    }
+
+    fn assert_plate(sheet: &Range<Data>, range: CellRange) {
+        assert_eq!(read_range(sheet, range), [[Some(0.1), Some(0.2), Some(0.3)], [Some(0.4), Some(0.5), Some(0.6)]]);
+    }
+
+    #[test]
+    fn reads_gen5() {
+        let sheet = export("Software Version\t3.11\nExperiment File Path:\tC:\\x.xpt\nDate\t10/17/2026\nTime\t10:21:05 AM\n\
+            Reader Serial Number:\t21051234\n\tWavelengths:  450\nActual Temperature:\t25.1\n\nResults");
+        let (range, info) = read_export(&sheet).unwrap();
+        assert_plate(&sheet, range);
+        assert_eq!(info, ReadInfo {
+            format: Some(ReaderFormat::Gen5),
+            wavelength: Some(450.0),
+            read_time: Some(String::from("10/17/2026 10:21:05 AM")),
+            serial: Some(String::from("21051234")),
+            temperature: Some(25.1),
+        });
+    }
+
+    #[test]
+    fn reads_tecan() {
+        let sheet = export("Application: Tecan i-control\nDevice: infinite 200Pro\tSerial number: 1506003049\n\
+            Measurement wavelength\t450\tnm\nStart Time:\t17.10.2026 10:21:05\nTemperature: 22,8 °C");
+        let (range, info) = read_export(&sheet).unwrap();
+        assert_plate(&sheet, range);
+        assert_eq!(info.format, Some(ReaderFormat::Tecan));
+        assert_eq!(info.wavelength, Some(450.0));
+        assert_eq!(info.read_time.as_deref(), Some("17.10.2026 10:21:05"));
+        assert_eq!(info.serial.as_deref(), Some("1506003049"));
+        assert_eq!(info.temperature, Some(22.8));
+    }
+
+    #[test]
+    fn reads_bmg() {
+        let header = "Date: 17/10/2026\nTime: 10:21:05\nCLARIOstar Plus 430-1234\nTarget temperature: 25.0\n\nRaw Data (450)";
+        let sheet = text_sheet(format!("{header}\n{}", PLATE.replace('\t', ";")).as_bytes());
+        let (range, info) = read_export(&sheet).unwrap();
+        assert_plate(&sheet, range);
+        assert_eq!(info.format, Some(ReaderFormat::Bmg));
+        assert_eq!(info.wavelength, Some(450.0));
+        assert_eq!(info.read_time.as_deref(), Some("17/10/2026 10:21:05"));
+        assert_eq!(info.temperature, Some(25.0));
+    }
+
+    #[test]
+    fn reads_softmax_pro() {
+        let export = "##BLOCKS= 1\nPlate:\tPlate1\t1.3\tPlateFormat\tEndpoint\tAbsorbance\tRaw\tFALSE\t1\t\t\t450\t1\t12\t96\n\
+            \tTemperature(°C)\t1\t2\t3\n\t25.30\t0.1\t0.2\t0.3\n\t\t0.4\t0.5\t0.6\n\n~End\n\
+            Original Filename: x; Date Last Saved: 10/17/2026 9:00:00 AM\n";
+        let sheet = text_sheet(export.as_bytes());
+        let (range, info) = read_export(&sheet).unwrap();
+        assert_eq!(range.address(), "C4:E5");
+        assert_plate(&sheet, range);
+        assert_eq!(info, ReadInfo {
+            format: Some(ReaderFormat::SoftMaxPro),
+            wavelength: Some(450.0),
+            read_time: Some(String::from("10/17/2026 9:00:00 AM")),
+            serial: None,
+            temperature: Some(25.3),
+        });
+    }
+
+    #[test]
+    fn unknown_exports_are_left_to_the_dialog() {
+        let sheet = export("Some other reader");
+        assert_eq!(ReaderFormat::detect(&sheet), None);
+        assert!(read_export(&sheet).is_none());
+        assert!(ReadInfo::default().fields().is_empty());
+    }
+}
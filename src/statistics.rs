@@ -0,0 +1,199 @@
+// Distribution functions, written out since we only need a handful of them
+
+/// Natural logarithm of the gamma function, Lanczos approximation
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146, -86.50532032941677, 24.01409824083091,
+        -1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate() {
+        series += coefficient / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Regularized incomplete beta function I_x(a, b)
+pub fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 { return 0.0 }
+    if x >= 1.0 { return 1.0 }
+
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only on one side of the mean
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+// Modified Lentz's method
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY { d = TINY }
+    d = 1.0 / d;
+    let mut result = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        let numerator = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + numerator * d;
+        if d.abs() < TINY { d = TINY }
+        c = 1.0 + numerator / c;
+        if c.abs() < TINY { c = TINY }
+        d = 1.0 / d;
+        result *= d * c;
+
+        let numerator = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + numerator * d;
+        if d.abs() < TINY { d = TINY }
+        c = 1.0 + numerator / c;
+        if c.abs() < TINY { c = TINY }
+        d = 1.0 / d;
+        let delta = d * c;
+        result *= delta;
+
+        if (delta - 1.0).abs() < 1e-15 { break }
+    }
+    result
+}
+
+/// Cumulative distribution function of Student's t distribution
+pub fn t_cdf(t: f64, degrees_of_freedom: f64) -> f64 {
+    let x = degrees_of_freedom / (degrees_of_freedom + t * t);
+    let tail = 0.5 * incomplete_beta(x, degrees_of_freedom / 2.0, 0.5);
+    if t > 0.0 { 1.0 - tail } else { tail }
+}
+
+/// Cumulative distribution function of the F distribution
+pub fn f_cdf(f: f64, numerator_df: f64, denominator_df: f64) -> f64 {
+    if f <= 0.0 { return 0.0 }
+    let x = numerator_df * f / (numerator_df * f + denominator_df);
+    incomplete_beta(x, numerator_df / 2.0, denominator_df / 2.0)
+}
+
+/// Quantile of Student's t distribution, found by bisection
+pub fn t_quantile(p: f64, degrees_of_freedom: f64) -> f64 {
+    if degrees_of_freedom.is_nan() || degrees_of_freedom <= 0.0 || !(0.0..=1.0).contains(&p) { return f64::NAN }
+    if p == 0.5 { return 0.0 }
+    if p < 0.5 { return -t_quantile(1.0 - p, degrees_of_freedom) }
+
+    let mut low = 0.0;
+    let mut high = 1.0;
+    while t_cdf(high, degrees_of_freedom) < p {
+        high *= 2.0;
+        if high > 1e10 { return f64::INFINITY }
+    }
+    for _ in 0..200 {
+        let middle = (low + high) / 2.0;
+        if t_cdf(middle, degrees_of_freedom) < p {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// SplitMix64, small and good enough for seeding and resampling, and reproducible across platforms
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniform in 0..n
+    pub fn next_index(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Linearly interpolated percentile of sorted values, p in [0, 1]
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() { return f64::NAN }
+    let position = p * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let t = position - lower as f64;
+    sorted[lower] * (1.0 - t) + sorted[upper] * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{actual} is not {expected} +- {tolerance}");
+    }
+
+    #[test]
+    fn ln_gamma_of_known_values() {
+        assert_close(ln_gamma(5.0), 24f64.ln(), 1e-10);
+        assert_close(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln(), 1e-10);
+    }
+
+    // Two-sided critical values from the t table
+    #[test]
+    fn t_quantile_matches_table() {
+        for (degrees_of_freedom, expected) in [(1.0, 12.706), (5.0, 2.571), (10.0, 2.228), (30.0, 2.042), (120.0, 1.980)] {
+            assert_close(t_quantile(0.975, degrees_of_freedom), expected, 1e-3);
+        }
+        assert_close(t_quantile(0.995, 20.0), 2.845, 1e-3);
+        assert_close(t_quantile(0.025, 10.0), -2.228, 1e-3);
+        assert_eq!(t_quantile(0.5, 7.0), 0.0);
+        assert!(t_quantile(0.975, 0.0).is_nan());
+    }
+
+    #[test]
+    fn t_cdf_is_symmetric() {
+        assert_close(t_cdf(0.0, 5.0), 0.5, 1e-12);
+        assert_close(t_cdf(2.0, 8.0) + t_cdf(-2.0, 8.0), 1.0, 1e-12);
+    }
+
+    // Upper 5% and 1% points from the F table
+    #[test]
+    fn f_cdf_matches_table() {
+        for (f, numerator_df, denominator_df) in [(161.45, 1.0, 1.0), (4.965, 1.0, 10.0), (4.103, 2.0, 10.0), (2.866, 4.0, 20.0)] {
+            assert_close(f_cdf(f, numerator_df, denominator_df), 0.95, 1e-4);
+        }
+        assert_close(f_cdf(5.953, 3.0, 12.0), 0.99, 1e-4);
+        assert_eq!(f_cdf(0.0, 2.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn percentile_interpolates() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 0.5), 2.5);
+        assert_eq!(percentile(&sorted, 1.0), 4.0);
+        assert!(percentile(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn split_mix_is_reproducible() {
+        let (mut a, mut b) = (SplitMix64(7), SplitMix64(7));
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        for _ in 0..100 {
+            assert!((0.0..1.0).contains(&a.next_f64()));
+            assert!(a.next_index(10) < 10);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::logistic_regression::*;
+
+/// Plate map without any measurements, to lay out every plate the same way
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub wells: Vec<Well>,
+    pub standard_groups: Vec<Group>,
+    pub unknown_groups: Vec<Group>,
+    pub quality_control_groups: Vec<Group>,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Well {
+    pub typ: SampleType,
+    pub group: usize,
+    #[serde(default)]
+    pub dilution: Option<f64>,
+}
+
+impl Template {
+    pub fn new(name: String, microplate: &Microplate) -> Self {
+        Self {
+            name,
+            width: microplate.width,
+            height: microplate.height,
+            wells: microplate.samples.iter().map(|&Sample { typ, group, dilution, .. }| Well { typ, group, dilution }).collect(),
+            standard_groups: microplate.standard_groups.clone(),
+            unknown_groups: microplate.unknown_groups.clone(),
+            quality_control_groups: microplate.quality_control_groups.clone(),
+        }
+    }
+
+    pub fn fits(&self, microplate: &Microplate) -> bool {
+        self.width == microplate.width && self.height == microplate.height
+            && self.wells.len() == self.width * self.height && microplate.samples.len() == self.wells.len()
+    }
+
+    /// Lays out the plate, its measurements and excluded wells stay
+    pub fn apply(&self, microplate: &mut Microplate) {
+        if !self.fits(microplate) { return }
+        for (sample, &Well { typ, group, dilution }) in microplate.samples.iter_mut().zip(&self.wells) {
+            sample.typ = typ;
+            sample.group = group;
+            sample.dilution = dilution;
+        }
+        microplate.standard_groups = self.standard_groups.clone();
+        microplate.unknown_groups = self.unknown_groups.clone();
+        microplate.quality_control_groups = self.quality_control_groups.clone();
+        // An edited template file may refer to groups it does not have
+        microplate.resize_groups();
+    }
+
+    /// Writes the template into the library, replacing one of the same name
+    pub fn save(&self) -> io::Result<()> {
+        let directory = template_directory().ok_or(io::ErrorKind::NotFound)?;
+        fs::create_dir_all(&directory)?;
+        let serialized = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(directory.join(file_name(&self.name)), serialized)
+    }
+
+    pub fn delete(&self) -> io::Result<()> {
+        let directory = template_directory().ok_or(io::ErrorKind::NotFound)?;
+        fs::remove_file(directory.join(file_name(&self.name)))
+    }
+}
+
+/// Every template in the library, sorted by name. Files that cannot be read are left out.
+pub fn load_templates() -> Vec<Template> {
+    let Some(entries) = template_directory().and_then(|directory| fs::read_dir(directory).ok()) else { return Vec::new() };
+    let mut templates: Vec<Template> = entries
+        .filter_map(|entry| fs::read(entry.ok()?.path()).ok())
+        .filter_map(|bytes| serde_json::from_slice(&bytes).ok())
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+/// Folder of the app in the configuration directory of the user
+pub fn config_directory() -> Option<PathBuf> {
+    use std::env::var_os;
+    let config = if cfg!(target_os = "windows") {
+        var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Application Support"))
+    } else {
+        var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config.map(|config| config.join("elisa"))
+}
+
+fn template_directory() -> Option<PathBuf> {
+    config_directory().map(|config| config.join("templates"))
+}
+
+// Characters that are not allowed in file names on some systems become underscores
+fn file_name(name: &str) -> String {
+    let name: String = name.chars().map(|char| if char.is_alphanumeric() || " -_.".contains(char) { char } else { '_' }).collect();
+    format!("{}.json", name.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::LayoutWizard;
+
+    #[test]
+    fn apply_keeps_the_measurements() {
+        let mut laid_out = Microplate::new(12, 8);
+        LayoutWizard::default().apply(&mut laid_out);
+        laid_out.standard_groups[0].label = String::from("Top");
+        let template = Template::new(String::from("Layout"), &laid_out);
+
+        let mut microplate = Microplate::new(12, 8);
+        for (i, sample) in microplate.samples.iter_mut().enumerate() {
+            sample.value = Some(i as f64 / 100.0);
+        }
+        microplate.samples[5].excluded = Some(String::from("Bubble"));
+        assert!(template.fits(&microplate));
+        template.apply(&mut microplate);
+
+        for (i, (sample, laid_out)) in microplate.samples.iter().zip(&laid_out.samples).enumerate() {
+            assert_eq!((sample.typ, sample.group), (laid_out.typ, laid_out.group));
+            assert_eq!(sample.value, Some(i as f64 / 100.0));
+        }
+        assert_eq!(microplate.samples[5].excluded.as_deref(), Some("Bubble"));
+        assert!(microplate.standard_groups == laid_out.standard_groups);
+        assert_eq!(microplate.unknown_groups.len(), laid_out.unknown_groups.len());
+    }
+
+    #[test]
+    fn fits_only_plates_of_its_size() {
+        let template = Template::new(String::from("96"), &Microplate::new(12, 8));
+        assert!(!template.fits(&Microplate::new(24, 16)));
+        assert!(!template.fits(&Microplate::new(8, 12)));
+
+        let mut microplate = Microplate::new(24, 16);
+        microplate.samples[0].typ = SampleType::Standard;
+        template.apply(&mut microplate);
+        assert_eq!(microplate.samples[0].typ, SampleType::Standard);
+
+        // A file with wells missing would leave part of the plate from before
+        let mut truncated = template.clone();
+        truncated.wells.pop();
+        assert!(!truncated.fits(&Microplate::new(12, 8)));
+    }
+
+    #[test]
+    fn apply_adds_groups_the_wells_refer_to() {
+        let mut template = Template::new(String::from("Edited"), &Microplate::new(12, 8));
+        template.wells[3] = Well { typ: SampleType::Standard, group: 4, dilution: None };
+        let mut microplate = Microplate::new(12, 8);
+        template.apply(&mut microplate);
+        assert_eq!(microplate.standard_groups.len(), 5);
+    }
+}
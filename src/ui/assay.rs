@@ -1,48 +1,45 @@
-use std::{cmp::Ordering::*, fmt::Display, fs::File, io::BufReader, num::ParseFloatError, path::PathBuf};
+use std::{fmt::Display, num::ParseFloatError, path::PathBuf};
 
-use calamine::{open_workbook, DataType, Reader, ReaderRef, Xlsx, XlsxError};
-use eframe::{egui::{self, text::LayoutJob, vec2, Align2, Color32, DragValue, FontFamily, FontId, Grid, Layout, Margin, Response, RichText, ScrollArea, Sense, Shape, Stroke, TextEdit, Ui, Vec2, Widget}, epaint};
+use calamine::{open_workbook, Reader, Xlsx};
+use eframe::{egui::{self, text::LayoutJob, vec2, Align2, Button, Color32, DragValue, FontFamily, FontId, Grid, Layout, Margin, Response, RichText, ScrollArea, Sense, Shape, Stroke, TextEdit, Ui, Vec2, Widget}, epaint};
 use egui_extras::{Column, TableBuilder};
 
-use crate::{*, logistic_regression::*};
+use crate::{*, acceptance::*, import::*, layout::*, limits::*, logistic_regression::*, outliers::*, potency::*, readers::*, templates::*, units::*};
 
-const ALPHABET: [char; 26] = [
-    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M',
-    'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z'
+const SAMPLE_TYPES: [(&str, SampleType); 6] = [
+    ("Unused", SampleType::Unused), ("Standard", SampleType::Standard), ("Control", SampleType::Control),
+    ("Unknown", SampleType::Unknown), ("Blank", SampleType::Blank), ("Quality Control", SampleType::QualityControl),
 ];
 
-struct SampleButton<'a> {
+struct SampleButton {
     sample: Sample,
     radius: f32,
-    current_value: &'a mut Option<usize>,
-    alternative: usize,
+    selected: bool,
 }
 
-impl<'a> SampleButton<'a> {
-    fn new(sample: Sample, radius: f32, current_value: &'a mut Option<usize>, alternative: usize) -> Self {
+impl SampleButton {
+    fn new(sample: Sample, radius: f32, selected: bool) -> Self {
         Self {
             sample,
             radius,
-            current_value,
-            alternative,
+            selected,
         }
     }
 }
 
-impl Widget for SampleButton<'_>{
+impl Widget for SampleButton {
     fn ui(self, ui: &mut Ui) -> Response {
         let Self {
             sample,
             radius,
-            current_value,
-            alternative,
+            selected,
         } = self;
 
         let min_size = 2.0 * Vec2::splat(radius);
-        let (response, painter) = ui.allocate_painter(min_size + Vec2::splat(4.0), Sense::click());
+        let (response, painter) = ui.allocate_painter(min_size + Vec2::splat(4.0), Sense::click_and_drag());
         let visuals = &ui.visuals().widgets;
 
-        let stroke = if Some(alternative) == *current_value {
+        let stroke = if selected {
             visuals.active.fg_stroke
         } else if response.hovered() {
             visuals.hovered.fg_stroke
@@ -56,32 +53,35 @@ impl Widget for SampleButton<'_>{
             stroke
         );
         let text = match sample.typ {
-            SampleType::Unknown | SampleType::Standard => true,
+            SampleType::Unknown | SampleType::Standard | SampleType::QualityControl => true,
             SampleType::Blank | SampleType::Unused | SampleType::Control => false,
         };
         
         if text {
+            let font_id = if radius >= 15.0 { FontId::default() } else { FontId::proportional(radius) };
             painter.text(
                 response.rect.center(),
                 Align2::CENTER_CENTER,
                 format!("{}", sample.group + 1),
-                FontId::default(),
+                font_id,
                 ui.visuals().text_color()
             );
         }
 
+        // Excluded wells stay on the plate, crossed out
+        if sample.excluded.is_some() && sample.typ != SampleType::Unused {
+            let rect = response.rect.shrink(4.0 + radius * (1.0 - std::f32::consts::FRAC_1_SQRT_2));
+            let stroke = Stroke::new(2.0, Color32::RED);
+            painter.line_segment([rect.left_top(), rect.right_bottom()], stroke);
+            painter.line_segment([rect.right_top(), rect.left_bottom()], stroke);
+        }
+
         response
     }
 }
 
 impl Elisa {
     pub fn measurements(&mut self, ui: &mut Ui) {
-        let microplate = &mut self.microplate;
-        let textfield = &mut self.data_textfield;
-        let data_sheets = &mut self.sheet_names;
-        let selected_sheet = &mut self.selected_sheet;
-        let excel = &mut self.excel;
-
         let width = 293.0;
         let space = 10.0;
         let stroke = ui.visuals().noninteractive().bg_stroke;
@@ -104,68 +104,29 @@ impl Elisa {
                                 Self::dashed_outline(ui, &button);
                                 if button.clicked() {
                                     if let Some(path) = rfd::FileDialog::new()
-                                        .add_filter("Excel Spreadsheet", &["xlsx"])
+                                        .add_filter("Plate reader export", &["xlsx", "txt", "csv"])
                                         .pick_file() {
-                                        match open_workbook::<Xlsx<_>, PathBuf>(path) {
-                                            Ok(mut xlsx) => {
-                                                *data_sheets = xlsx.sheet_names();
-                                                if data_sheets.is_empty() {
-                                                    todo!();
-                                                }
-                                                match Elisa::parse_xlsx_sheet(&mut xlsx, *selected_sheet) {
-                                                    Ok(data) => {
-                                                        let string = Elisa::data_to_string(data); 
-                                                        *textfield = string;
-                                                    },
-                                                    Err(error) => eprintln!("error parsing excel sheet: {}", error)
-                                                }
-                                                *excel = Some(xlsx);  
-                                            }
-                                            Err(err) => eprintln!("Could not load excel spreadsheet: {err}"),
-                                        }
+                                        self.open_export(path);
                                     }
                                 }
                             });
+                            // Picking another block of the same file
+                            if self.import_sheet.is_some() {
+                                let button = ui.button("Import");
+                                Self::dashed_outline(ui, &button);
+                                if button.clicked() {
+                                    self.import_profiles = load_profiles();
+                                    self.import_modal = true;
+                                }
+                            }
 
                             ui.add_space(space);
                             ui.label(RichText::new("or edit manually:").size(15.0));
                         });
                         ui.add_space(space);
-                        if let Some(excel) = excel {
-                            match data_sheets.len().cmp(&1) {
-                                Greater => {
-                                    ScrollArea::horizontal().max_height(20.0).id_salt("Sheets").show(ui, |ui| {
-                                        ui.horizontal(|ui| {
-                                            for (i, sheet) in data_sheets.iter().enumerate() {
-                                                if ui.radio_value(selected_sheet, i, sheet).clicked() {
-                                                    match Elisa::parse_xlsx_sheet(excel, *selected_sheet) {
-                                                        Ok(data) => {
-                                                           let string = Elisa::data_to_string(data);
-                                                           *textfield = string;
-                                                        },
-                                                        Err(error) => eprintln!("Error parsing excel sheet: {}", error)
-                                                    }
-                                                }
-                                                ui.add_space(space);
-                                            }
-                                        });
-                                        ui.add_space(space);
-                                    });
-                                },
-                                Equal => {
-                                    match Elisa::parse_xlsx_sheet(excel, *selected_sheet) {
-                                        Ok(data) => {
-                                               let string = Elisa::data_to_string(data); 
-                                               *textfield = string;
-                                        },
-                                        Err(error) => eprintln!("error parsing excel sheet: {}", error)
-                                    }
-                                },
-                                Less => ()
-                
-                            }
-                        }
 
+                        let microplate = &mut self.microplate;
+                        let textfield = &mut self.data_textfield;
                         let mut layouter = |ui: &egui::Ui, string: &str, _wrap_width: f32| {
                             let font_id = FontId::monospace(12.0);
                             let layout_job = LayoutJob::simple(string.to_owned(), font_id, Color32::BLACK, f32::INFINITY);
@@ -213,58 +174,134 @@ impl Elisa {
     
     pub fn microplate_view(&mut self, ui: &mut Ui) {
         let microplate = &mut self.microplate;
-        let radius = 30.0 / 2.0;
-        let spacing = 10.0 - 4.0;
+        // Wells shrink with the format to fit the space of a 96-well plate, smaller plates get bigger wells.
+        // Below the smallest size, the plate scrolls.
+        let radius = (15.0 * 12.0 / microplate.width as f32).min(15.0 * 8.0 / microplate.height as f32).clamp(6.0, 30.0);
+        let spacing = (0.4 * radius).min(10.0 - 4.0);
         let cell_size = 2.0 * Vec2::splat(radius);
+        let font_size = radius.max(8.0);
         let response_color = ui.visuals().text_color();
 
+        let height = microplate.height;
+        let modifiers = ui.input(|i| i.modifiers);
+        let selected = &self.selected_samples;
+        let mut wells = Vec::with_capacity(microplate.samples.len()); // index and rect of every well
+        let mut clicked = None;
+        let mut header_clicked = None;
+
         let where_to_put_background = ui.painter().add(Shape::Noop);
         
         let frame_response = egui::Frame::new().inner_margin(Margin { right: 17, bottom: 17, ..default()}).show(ui, |ui| {
-            Grid::new("Microplate")
-                .spacing(Vec2::splat(spacing))
-                .min_col_width(radius + spacing / 2.0)
-                .max_col_width(radius + spacing / 2.0)
-                .min_row_height(radius + spacing / 2.0)
-                .show(ui, |ui| {
-                    ui.allocate_exact_size(cell_size, Sense::hover());
-                    for i in 1..=microplate.width {
-                        let (response, painter) = ui.allocate_painter(cell_size, Sense::hover());
-                        painter.text(
-                            response.rect.center(),
-                            Align2::CENTER_TOP,
-                            format!("{i}"),
-                            FontId::new(radius, FontFamily::default()),
-                            response_color
-                        );
-                    }
-                    ui.end_row();
-                    for i in 0..microplate.height {
-                        let (response, painter) = ui.allocate_painter(cell_size, Sense::hover());
-                        painter.text(
-                            response.rect.center(),
-                            Align2::LEFT_CENTER,
-                            ALPHABET[i%26],
-                            FontId::new(radius, FontFamily::default()),
-                            response_color
-                        );
-                        for ii in 0..microplate.width {
-                            let index = ii * microplate.height + i;
-                            let sample = microplate.samples[index].clone();
-                            let response = ui.add(SampleButton::new(sample, radius, &mut self.selected_sample, index));
+            ScrollArea::both().id_salt("Microplate Scroll").max_width(470.0).max_height(325.0).drag_to_scroll(false).show(ui, |ui| {
+                Grid::new("Microplate")
+                    .spacing(Vec2::splat(spacing))
+                    .min_col_width(radius + spacing / 2.0)
+                    .max_col_width(radius + spacing / 2.0)
+                    .min_row_height(radius + spacing / 2.0)
+                    .show(ui, |ui| {
+                        // The corner selects the whole plate, the headers a column or a row
+                        let (_, response) = ui.allocate_exact_size(cell_size, Sense::click());
+                        if response.clicked() {
+                            header_clicked = Some((0..microplate.samples.len()).collect::<Vec<_>>());
+                        }
+                        for i in 1..=microplate.width {
+                            let (response, painter) = ui.allocate_painter(cell_size, Sense::click());
                             if response.clicked() {
-                                if self.selected_sample == Some(index) {
-                                    self.selected_sample = None;
-                                } else {
-                                    self.selected_sample = Some(index);
-                                }
+                                header_clicked = Some(((i - 1) * height..i * height).collect());
                             }
+                            painter.text(
+                                response.rect.center(),
+                                Align2::CENTER_TOP,
+                                format!("{i}"),
+                                FontId::new(font_size, FontFamily::default()),
+                                response_color
+                            );
                         }
                         ui.end_row();
-                    }
-                });
+                        for i in 0..microplate.height {
+                            let (response, painter) = ui.allocate_painter(cell_size, Sense::click());
+                            if response.clicked() {
+                                header_clicked = Some((0..microplate.width).map(|column| column * height + i).collect());
+                            }
+                            painter.text(
+                                response.rect.center(),
+                                Align2::LEFT_CENTER,
+                                Microplate::row_name(i),
+                                FontId::new(font_size, FontFamily::default()),
+                                response_color
+                            );
+                            for ii in 0..microplate.width {
+                                let index = ii * microplate.height + i;
+                                let sample = microplate.samples[index].clone();
+                                let response = ui.add(SampleButton::new(sample, radius, selected.contains(&index)));
+                                if response.drag_started() {
+                                    self.drag_start = Some(index);
+                                    self.drag_base = if modifiers.command { selected.clone() } else { Vec::new() };
+                                }
+                                if response.clicked() {
+                                    clicked = Some(index);
+                                }
+                                wells.push((index, response.rect));
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
         });
 
+        // Dragging selects the rectangle between the first well and the one under the pointer, or paints every well it passes
+        let pointer = ui.input(|i| i.pointer.interact_pos());
+        let hovered = pointer.and_then(|pointer| wells.iter().find(|(_, rect)| rect.contains(pointer)).map(|&(index, _)| index));
+        if let Some(start) = self.drag_start {
+            if !ui.input(|i| i.pointer.primary_down()) {
+                self.drag_start = None;
+            } else if self.paint_mode {
+                self.paint(start);
+                if let Some(end) = hovered { self.paint(end) }
+            } else if let Some(end) = hovered {
+                let mut selection = self.drag_base.clone();
+                for index in Self::well_rectangle(height, start, end) {
+                    selection.retain(|&selected| selected != index);
+                    selection.push(index);
+                }
+                self.selected_samples = selection;
+            }
+        }
+
+        if let Some(index) = clicked {
+            let selection = &mut self.selected_samples;
+            if self.paint_mode {
+                self.paint(index);
+            } else if modifiers.shift && !selection.is_empty() {
+                let anchor = *selection.last().unwrap();
+                for index in Self::well_rectangle(height, anchor, index) {
+                    selection.retain(|&selected| selected != index);
+                    selection.push(index);
+                }
+            } else if modifiers.command {
+                if selection.contains(&index) {
+                    selection.retain(|&selected| selected != index);
+                } else {
+                    selection.push(index);
+                }
+            } else if *selection == [index] {
+                selection.clear();
+            } else {
+                *selection = vec![index];
+            }
+        }
+        if let Some(wells) = header_clicked {
+            if self.paint_mode {
+                wells.into_iter().for_each(|index| self.paint(index));
+            } else {
+                if !modifiers.command { self.selected_samples.clear() }
+                for index in wells {
+                    self.selected_samples.retain(|&selected| selected != index);
+                    self.selected_samples.push(index);
+                }
+            }
+        }
+
         let fill = ui.visuals().faint_bg_color;
         let stroke = ui.visuals().widgets.noninteractive.bg_stroke;
 
@@ -280,8 +317,33 @@ impl Elisa {
         ui.painter().set(where_to_put_background, shape);
     }
     
+    /// Wells between two corners, ending with the second one
+    fn well_rectangle(height: usize, from: usize, to: usize) -> Vec<usize> {
+        let (from_row, from_column) = (from % height, from / height);
+        let (to_row, to_column) = (to % height, to / height);
+        let mut wells: Vec<_> = (from_column.min(to_column)..=from_column.max(to_column))
+            .flat_map(|column| (from_row.min(to_row)..=from_row.max(to_row)).map(move |row| column * height + row))
+            .filter(|&index| index != to)
+            .collect();
+        wells.push(to);
+        wells
+    }
+
+    /// Gives the well the type and group of the brush
+    fn paint(&mut self, index: usize) {
+        let (typ, group) = self.brush;
+        let sample = &mut self.microplate.samples[index];
+        if (sample.typ, sample.group) == (typ, group) { return }
+        sample.typ = typ;
+        sample.group = group;
+        self.microplate.resize_groups();
+    }
+
     pub fn sample_menu(&mut self, ui: &mut Ui) {
         let radius = 15.0;
+        // The menu edits the last selected well, the rest of the selection follows afterwards
+        let selection = self.selected_samples.clone();
+        let before = selection.last().map(|&index| (self.microplate.samples[index].clone(), self.microplate.group_mut(index).cloned()));
         let samples = &mut self.microplate.samples;
         let stroke = ui.visuals().noninteractive().bg_stroke;
         let fill = ui.visuals().faint_bg_color;
@@ -298,11 +360,51 @@ impl Elisa {
                     .show(ui, |ui| {
                         ui.set_width(width - 20.0);
                         ui.set_min_height(195.0);
-                        if let Some(index) = self.selected_sample {
+                        ui.horizontal(|ui| {
+                            let select = ui.selectable_label(!self.paint_mode, "Select");
+                            Self::dashed_outline(ui, &select);
+                            if select.clicked() { self.paint_mode = false }
+                            ui.add_space(10.0);
+                            let paint = ui.selectable_label(self.paint_mode, "Paint");
+                            Self::dashed_outline(ui, &paint);
+                            if paint.clicked() { self.paint_mode = true }
+                            ui.add_space(10.0);
+                            let wizard = ui.button("Layout wizard");
+                            Self::dashed_outline(ui, &wizard);
+                            if wizard.clicked() { self.layout_modal = true }
+                        });
+                        ui.add_space(10.0);
+
+                        if self.paint_mode {
+                            let (typ, group) = &mut self.brush;
+                            ui.horizontal(|ui| {
+                                ui.label("Brush");
+                                ui.add_space(10.0);
+                                let menu_button = ui.menu_button(format!("{typ:?}"), |ui| {
+                                    for (name, alternative) in SAMPLE_TYPES {
+                                        if ui.button(name).clicked() { *typ = alternative }
+                                    }
+                                });
+                                Self::dashed_outline(ui, &menu_button.response);
+                                if matches!(typ, SampleType::Standard | SampleType::Unknown | SampleType::QualityControl) {
+                                    ui.add_space(10.0);
+                                    let mut number = *group + 1;
+                                    let drag_value = ui.add(DragValue::new(&mut number).speed(0.03).range(1..=samples.len()).prefix("Group "));
+                                    Self::dashed_outline(ui, &drag_value);
+                                    *group = number - 1;
+                                }
+                            });
+                            ui.add_space(10.0);
+                            ui.label("Click or drag over the wells to paint them.");
+                        } else if let Some(&index) = selection.last() {
                             use SampleType::*;
                             
                             ui.horizontal(|ui| {
-                                ui.label(format!("Selected sample {}", index + 1));
+                                if selection.len() > 1 {
+                                    ui.label(format!("{} samples selected", selection.len()));
+                                } else {
+                                    ui.label(format!("Selected sample {}", index + 1));
+                                }
 
                                 let (response, painter) = ui.allocate_painter(vec2(ui.available_width(), 2.0 * radius), Sense::hover());
                                 painter.circle(response.rect.right_center() - vec2(2.0 * radius - 10.0, 0.0), radius, samples[index].typ.color(), Stroke::NONE);
@@ -315,14 +417,27 @@ impl Elisa {
                             let mut list = vec!["Sample Type", "Measurement"];
                             match samples[index].typ {
                                 Standard => {
-                                    list.push("Group")
+                                    list.push("Group");
+                                    list.push("Concentration");
                                 },
                                 Unknown => {
                                     list.push("Group");
                                     list.push("Label");
+                                    list.push("Dose");
+                                    list.push("Dilution");
+                                    list.push("Well dilution");
+                                }
+                                QualityControl => {
+                                    list.push("Group");
+                                    list.push("Label");
+                                    list.push("Nominal");
+                                    list.push("Tolerance");
                                 }
                                 _ => ()
                             }
+                            if samples[index].typ != Unused {
+                                list.push("Excluded");
+                            }
 
                             // Building two tables with different alignment is suboptimal
                             ui.horizontal_top(|ui| {
@@ -344,11 +459,9 @@ impl Elisa {
                                             row.col(|ui| {
                                                 ui.horizontal_centered(|ui| {
                                                     let menu_button = ui.menu_button(format!("{:?}", samples[index].typ), |ui| {
-                                                        if ui.button("Unused").clicked() { samples[index].typ = Unused }
-                                                        if ui.button("Standard").clicked() { samples[index].typ = Standard }
-                                                        if ui.button("Control").clicked() { samples[index].typ = Control }
-                                                        if ui.button("Unknown").clicked() { samples[index].typ = Unknown }
-                                                        if ui.button("Blank").clicked() { samples[index].typ = Blank }
+                                                        for (name, alternative) in SAMPLE_TYPES {
+                                                            if ui.button(name).clicked() { samples[index].typ = alternative }
+                                                        }
                                                     });
                                                     Self::dashed_outline(ui, &menu_button.response);
                                                 });
@@ -363,12 +476,12 @@ impl Elisa {
                                             });                                        
                                         });
 
-                                        if samples[index].typ == Unknown || samples[index].typ == Standard {
+                                        if matches!(samples[index].typ, Unknown | Standard | QualityControl) {
                                             body.row(row_height, |mut row| {
                                                 row.col(|ui| {
                                                     ui.horizontal_centered(|ui| {
                                                         self.selected_sample_group = samples[index].group + 1;
-                                                        let drag_value = DragValue::new(&mut self.selected_sample_group).speed(0.03).range(1..=100);
+                                                        let drag_value = DragValue::new(&mut self.selected_sample_group).speed(0.03).range(1..=samples.len());
                                                         let mut drag_value_resp = ui.add(drag_value);
                                                         samples[index].group = self.selected_sample_group - 1;
                                                 
@@ -397,6 +510,29 @@ impl Elisa {
                                                         .map(|sample| sample.group)
                                                         .max().unwrap_or_default();
                                                     self.microplate.unknown_groups.resize_with(max_unknown_group + 1, default);
+
+                                                    let max_quality_control_group = samples.iter()
+                                                        .filter(|sample| sample.typ == SampleType::QualityControl)
+                                                        .map(|sample| sample.group)
+                                                        .max().unwrap_or_default();
+                                                    self.microplate.quality_control_groups.resize_with(max_quality_control_group + 1, default);
+                                                });
+                                            });
+                                        }
+
+                                        if samples[index].typ == Standard {
+                                            body.row(row_height, |mut row| {
+                                                row.col(|ui| {
+                                                    ui.horizontal_centered(|ui| {
+                                                        let concentration = &mut self.microplate.standard_groups[samples[index].group].concentration;
+                                                        let mut value = concentration.unwrap_or_default();
+                                                        let speed = (value * 0.01).max(0.01);
+                                                        let drag_value = ui.add(DragValue::new(&mut value).speed(speed).range(0.0..=f64::MAX));
+                                                        Self::dashed_outline(ui, &drag_value);
+                                                        if drag_value.changed() {
+                                                            *concentration = Some(value);
+                                                        }
+                                                    });
                                                 });
                                             });
                                         }
@@ -412,6 +548,110 @@ impl Elisa {
                                                     });
                                                 });
                                             });
+                                            // Unknowns with a dose and the same label are a dilution series for relative potency
+                                            body.row(row_height, |mut row| {
+                                                row.col(|ui| {
+                                                    ui.horizontal_centered(|ui| {
+                                                        let dose = &mut self.microplate.unknown_groups[samples[index].group].concentration;
+                                                        let mut has_dose = dose.is_some();
+                                                        if ui.checkbox(&mut has_dose, "").changed() {
+                                                            *dose = has_dose.then_some(1.0);
+                                                        }
+                                                        if let Some(dose) = dose {
+                                                            let speed = *dose * 0.01;
+                                                            let drag_value = ui.add(DragValue::new(dose).speed(speed).range(0.0..=f64::MAX));
+                                                            Self::dashed_outline(ui, &drag_value);
+                                                        }
+                                                    });
+                                                });
+                                            });
+                                            body.row(row_height, |mut row| {
+                                                row.col(|ui| {
+                                                    ui.horizontal_centered(|ui| {
+                                                        let dilution = &mut self.microplate.unknown_groups[samples[index].group].dilution;
+                                                        let mut factor = dilution.unwrap_or(1.0);
+                                                        let drag_value = ui.add(DragValue::new(&mut factor).speed(0.1).range(1.0..=f64::MAX).prefix("x "));
+                                                        Self::dashed_outline(ui, &drag_value);
+                                                        if drag_value.changed() {
+                                                            *dilution = (factor != 1.0).then_some(factor);
+                                                        }
+                                                    });
+                                                });
+                                            });
+                                            // A single well can be diluted differently from the rest of its group
+                                            body.row(row_height, |mut row| {
+                                                row.col(|ui| {
+                                                    ui.horizontal_centered(|ui| {
+                                                        let group_dilution = self.microplate.unknown_groups[samples[index].group].dilution.unwrap_or(1.0);
+                                                        let dilution = &mut samples[index].dilution;
+                                                        let mut own = dilution.is_some();
+                                                        if ui.checkbox(&mut own, "").changed() {
+                                                            *dilution = own.then_some(group_dilution);
+                                                        }
+                                                        if let Some(factor) = dilution {
+                                                            let drag_value = ui.add(DragValue::new(factor).speed(0.1).range(1.0..=f64::MAX).prefix("x "));
+                                                            Self::dashed_outline(ui, &drag_value);
+                                                        }
+                                                    });
+                                                });
+                                            });
+                                        }
+
+                                        if samples[index].typ == QualityControl {
+                                            let group = &mut self.microplate.quality_control_groups[samples[index].group];
+                                            body.row(row_height, |mut row| {
+                                                row.col(|ui| {
+                                                    ui.horizontal_centered(|ui| {
+                                                        let mut text_edit = ui.add(TextEdit::singleline(&mut group.label).desired_width(100.0));
+                                                        text_edit.rect = text_edit.rect.expand2(vec2(4.0, 2.0));
+                                                        Self::dashed_outline(ui, &text_edit);
+                                                    });
+                                                });
+                                            });
+                                            body.row(row_height, |mut row| {
+                                                row.col(|ui| {
+                                                    ui.horizontal_centered(|ui| {
+                                                        let mut nominal = group.concentration.unwrap_or_default();
+                                                        let speed = (nominal * 0.01).max(0.01);
+                                                        let drag_value = ui.add(DragValue::new(&mut nominal).speed(speed).range(0.0..=f64::MAX));
+                                                        Self::dashed_outline(ui, &drag_value);
+                                                        if drag_value.changed() {
+                                                            group.concentration = Some(nominal);
+                                                        }
+                                                    });
+                                                });
+                                            });
+                                            // Without a tolerance of its own, the level uses the one of the QC rule
+                                            body.row(row_height, |mut row| {
+                                                row.col(|ui| {
+                                                    ui.horizontal_centered(|ui| {
+                                                        let mut own = group.tolerance.is_some();
+                                                        if ui.checkbox(&mut own, "").changed() {
+                                                            group.tolerance = own.then_some(20.0);
+                                                        }
+                                                        if let Some(tolerance) = &mut group.tolerance {
+                                                            let drag_value = ui.add(DragValue::new(tolerance).speed(0.5).range(0.0..=100.0).prefix("+- ").suffix("%"));
+                                                            Self::dashed_outline(ui, &drag_value);
+                                                        }
+                                                    });
+                                                });
+                                            });
+                                        }
+
+                                        if samples[index].typ != Unused {
+                                            body.row(row_height, |mut row| {
+                                                row.col(|ui| {
+                                                    ui.horizontal_centered(|ui| {
+                                                        let excluded = &mut samples[index].excluded;
+                                                        let mut checked = excluded.is_some();
+                                                        let text = excluded.clone().unwrap_or_default();
+                                                        let checkbox = ui.checkbox(&mut checked, text);
+                                                        if checkbox.changed() {
+                                                            *excluded = checked.then(|| String::from("Excluded manually"));
+                                                        }
+                                                    });
+                                                });
+                                            });
                                         }
                                     });
                             });
@@ -420,10 +660,45 @@ impl Elisa {
                         }
                 });
             });
-        });            
+        });
+
+        if let Some((before, group_before)) = before {
+            self.apply_to_selection(&selection, before, group_before);
+        }
+    }
+
+    /// Copies what the sample menu changed on the last selected well to the rest of the selection
+    fn apply_to_selection(&mut self, selection: &[usize], before: Sample, group_before: Option<Group>) {
+        let Some((&index, others)) = selection.split_last() else { return };
+        if others.is_empty() { return }
+        let microplate = &mut self.microplate;
+        let after = microplate.samples[index].clone();
+        for &other in others {
+            let sample = &mut microplate.samples[other];
+            if after.typ != before.typ { sample.typ = after.typ }
+            if after.group != before.group { sample.group = after.group }
+            if after.dilution != before.dilution { sample.dilution = after.dilution }
+            if after.excluded != before.excluded { sample.excluded = after.excluded.clone() }
+        }
+        microplate.resize_groups();
+
+        // Labels and concentrations belong to the groups, so the groups of the other selected wells of the same type change
+        if (after.typ, after.group) != (before.typ, before.group) { return }
+        let (Some(group_before), Some(group_after)) = (group_before, microplate.group_mut(index).cloned()) else { return };
+        for &other in others {
+            if microplate.samples[other].typ != after.typ { continue }
+            let Some(group) = microplate.group_mut(other) else { continue };
+            if group_after.label != group_before.label { group.label = group_after.label.clone() }
+            if group_after.concentration != group_before.concentration { group.concentration = group_after.concentration }
+            if group_after.dilution != group_before.dilution { group.dilution = group_after.dilution }
+            if group_after.tolerance != group_before.tolerance { group.tolerance = group_after.tolerance }
+        }
     }
     
     pub fn standards_concentrations(&mut self, ui: &mut Ui) {
+        // Groups come from painting, templates and project files too, every one needs its text field
+        self.standards_textfield.resize(self.microplate.standard_groups.len(), String::new());
+        let plate_unit = self.microplate.unit;
         let groups = &mut self.microplate.standard_groups;
         
         let stroke = ui.visuals().noninteractive().bg_stroke;
@@ -449,9 +724,11 @@ impl Elisa {
                                     .min_scrolled_height(height - 20.0)
                                     .max_scroll_height(height - 20.0)
                                     .columns(Column::exact(90.0), 2)
+                                    .column(Column::exact(70.0))
                                     .header(20.0, |mut header| {
                                         header.col(|ui| { ui.label("Group"); });
                                         header.col(|ui| { ui.label("Concentrations"); });
+                                        header.col(|ui| { ui.label("Unit"); });
                                     })
                                     .body(|body| {
                                         body.rows(25.0, groups.len(), |mut row| {
@@ -467,6 +744,18 @@ impl Elisa {
                                                 Self::dashed_outline(ui, &text_edit);    
                                             });
                                             groups[index].concentration = text_edit.parse().ok();
+                                            // Standards in another unit are converted to the unit of the microplate
+                                            row.col(|ui| {
+                                                let unit = &mut groups[index].unit;
+                                                let menu_button = ui.menu_button(unit.unwrap_or(plate_unit).name(), |ui| {
+                                                    for alternative in Unit::ALL {
+                                                        if ui.button(alternative.name()).clicked() {
+                                                            *unit = (alternative != plate_unit).then_some(alternative);
+                                                        }
+                                                    }
+                                                });
+                                                Self::dashed_outline(ui, &menu_button.response);
+                                            });
                                         });
                                     });
                             });
@@ -513,6 +802,7 @@ impl Elisa {
         let space = 10.0;
         let stroke = ui.visuals().noninteractive().bg_stroke;
         let fill = ui.visuals().faint_bg_color;
+        let mut calculate = false;
 
         ui.vertical(|ui| {
             egui::Frame::new().show(ui, |ui| {
@@ -534,11 +824,57 @@ impl Elisa {
                             Self::dashed_outline(ui, &text_edit);
                         });
 
+                        ui.add_space(space);
+                        ui.horizontal(|ui| {
+                            ui.label("Format");
+                            // A new format starts an empty plate with the same settings
+                            let menu_button = ui.menu_button(microplate.format_name(), |ui| {
+                                for (width, height) in Microplate::FORMATS {
+                                    if ui.button(format!("{} wells", width * height)).clicked() && (width, height) != (microplate.width, microplate.height) {
+                                        let Microplate { name, description, model, fit_options, unit, molecular_weight, acceptance, .. } = std::mem::take(microplate);
+                                        *microplate = Microplate { name, description, model, fit_options, unit, molecular_weight, acceptance, ..Microplate::new(width, height) };
+                                        self.selected_samples.clear();
+                                    }
+                                }
+                            });
+                            Self::dashed_outline(ui, &menu_button.response);
+                        });
+                        ui.add_space(space);
+                        ui.horizontal(|ui| {
+                            ui.label("Unit");
+                            let menu_button = ui.menu_button(microplate.unit.name(), |ui| {
+                                for unit in Unit::ALL {
+                                    if ui.button(unit.name()).clicked() { microplate.unit = unit }
+                                }
+                            });
+                            Self::dashed_outline(ui, &menu_button.response);
+                        });
+                        ui.add_space(space);
+                        ui.horizontal(|ui| {
+                            ui.label("MW");
+                            let mut has_weight = microplate.molecular_weight.is_some();
+                            if ui.checkbox(&mut has_weight, "").changed() {
+                                microplate.molecular_weight = has_weight.then_some(50000.0);
+                            }
+                            if let Some(weight) = &mut microplate.molecular_weight {
+                                let drag_value = ui.add(DragValue::new(weight).speed(10.0).range(1.0..=f64::MAX).suffix(" g/mol"));
+                                Self::dashed_outline(ui, &drag_value);
+                            }
+                        });
+                        // Details of the imported read, all of them on hover
+                        let fields = microplate.read_info.fields();
+                        if !fields.is_empty() {
+                            ui.add_space(5.0);
+                            let summary: Vec<_> = fields.iter().take(3).map(|(_, value)| value.as_str()).collect();
+                            let details: Vec<_> = fields.iter().map(|(label, value)| format!("{label}: {value}")).collect();
+                            ui.label(RichText::new(summary.join(", ")).size(11.0)).on_hover_text(details.join("\n"));
+                        }
+
                         ui.add_space(space);
                         ui.label("Description");
                         ui.add_space(5.0);
                         let scroll_area = egui::ScrollArea::vertical()
-                            .max_height(ui.available_height() - 40.0)
+                            .max_height(ui.available_height() - 75.0)
                             .show(ui, |ui| {
                                 ui.add(TextEdit::multiline(&mut microplate.description).desired_rows(8))
                             });
@@ -548,22 +884,770 @@ impl Elisa {
                         Self::dashed_outline(ui, &text_edit);
                         ui.add_space(space);
 
-                        let button = ui.button("Calculate");
-                        Self::dashed_outline(ui, &button);
-                        if button.clicked() {
-                            match Regression::new(microplate) {
-                                Ok(regression) => {
-                                    self.regression = Some(regression);
-                                    self.current_tab = ElisaTab::Result;
-                                },
-                                Err(error) => { self.value_error_modal = Some(error) }
+                        let button = ui.horizontal(|ui| {
+                            let button = ui.button("Calculate");
+                            Self::dashed_outline(ui, &button);
+                            ui.add_space(space);
+
+                            let menu_button = ui.menu_button(microplate.model.name(), |ui| {
+                                for model in Model::ALL {
+                                    if ui.button(model.name()).clicked() { microplate.model = model }
+                                }
+                            });
+                            Self::dashed_outline(ui, &menu_button.response);
+                            ui.add_space(space);
+
+                            let options = ui.button("Options");
+                            Self::dashed_outline(ui, &options);
+                            if options.clicked() {
+                                self.fit_options_modal = true;
                             }
-                        }
+                            button
+                        }).inner;
+                        ui.add_space(space);
+                        ui.horizontal(|ui| {
+                            let outliers = ui.button("Outliers");
+                            Self::dashed_outline(ui, &outliers);
+                            if outliers.clicked() {
+                                self.outlier_modal = true;
+                            }
+                            ui.add_space(space);
+                            let templates = ui.button("Templates");
+                            Self::dashed_outline(ui, &templates);
+                            if templates.clicked() {
+                                self.templates = load_templates();
+                                self.template_modal = true;
+                            }
+                        });
+                        calculate = button.clicked();
                     });
             });
         });
+
+        if calculate {
+            match self.calculate() {
+                Ok(()) => self.current_tab = ElisaTab::Result,
+                Err(error) => self.value_error_modal = Some(error),
+            }
+        }
     }
     
+    pub fn fit_options(&mut self, ui: &mut Ui) {
+        if !self.fit_options_modal { return }
+        let options = &mut self.microplate.fit_options;
+        let acceptance = &mut self.microplate.acceptance;
+
+        egui::Modal::new(egui::Id::new("Fit Options")).show(ui.ctx(), |ui| {
+            ui.vertical(|ui| {
+                ui.set_width(300.0);
+                ui.spacing_mut().item_spacing = vec2(10.0, 5.0);
+                ui.heading("Fit Options");
+                ui.add_space(10.0);
+
+                let multi_start = &mut options.multi_start;
+                ui.checkbox(&mut multi_start.enabled, "Multi-start search");
+                ui.add_enabled_ui(multi_start.enabled, |ui| {
+                    Grid::new("Multi-start").num_columns(2).spacing(vec2(20.0, 5.0)).show(ui, |ui| {
+                        ui.label("Starts");
+                        ui.add(DragValue::new(&mut multi_start.starts).range(1..=500));
+                        ui.end_row();
+                        ui.label("Spread");
+                        ui.add(DragValue::new(&mut multi_start.spread).speed(0.01).range(0.05..=5.0));
+                        ui.end_row();
+                        ui.label("Seed");
+                        ui.add(DragValue::new(&mut multi_start.seed));
+                        ui.end_row();
+                    });
+                });
+
+                ui.add_space(10.0);
+                ui.checkbox(&mut options.fit_replicates, "Fit individual replicates");
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let direction = &mut options.direction;
+                    ui.label("Direction");
+                    ui.menu_button(direction.name(), |ui| {
+                        for alternative in [Direction::Auto, Direction::Increasing, Direction::Decreasing] {
+                            if ui.button(alternative.name()).clicked() { *direction = alternative }
+                        }
+                    });
+                });
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let weighting = &mut options.weighting;
+                    ui.label("Weighting");
+                    ui.menu_button(weighting.name(), |ui| {
+                        if ui.button("None").clicked() { *weighting = Weighting::None }
+                        if ui.button("1/Y").clicked() { *weighting = Weighting::InverseY }
+                        if ui.button("1/Y^2").clicked() { *weighting = Weighting::InverseYSquared }
+                        if ui.button("1/X^2").clicked() { *weighting = Weighting::InverseXSquared }
+                        if ui.button("1/Y^theta").clicked() { *weighting = Weighting::Power(1.0) }
+                        if ui.button("Power of mean").clicked() { *weighting = Weighting::EstimatedPower }
+                    });
+                    if let Weighting::Power(theta) = weighting {
+                        ui.label("theta");
+                        ui.add(DragValue::new(theta).speed(0.01).range(0.0..=4.0));
+                    }
+                });
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let intervals = &mut options.intervals;
+                    ui.label("Confidence intervals");
+                    let name = if let IntervalMethod::Bootstrap { .. } = intervals { "Bootstrap" } else { "Asymptotic" };
+                    ui.menu_button(name, |ui| {
+                        if ui.button("Asymptotic").clicked() { *intervals = IntervalMethod::Asymptotic }
+                        if ui.button("Bootstrap").clicked() { *intervals = IntervalMethod::Bootstrap { resamples: 1000, seed: 1 } }
+                    });
+                });
+                if let IntervalMethod::Bootstrap { resamples, seed } = &mut options.intervals {
+                    Grid::new("Bootstrap").num_columns(2).spacing(vec2(20.0, 5.0)).show(ui, |ui| {
+                        ui.label("Resamples");
+                        ui.add(DragValue::new(resamples).range(50..=10000));
+                        ui.end_row();
+                        ui.label("Seed");
+                        ui.add(DragValue::new(seed));
+                        ui.end_row();
+                    });
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let parallelism = &mut options.parallelism;
+                    ui.label("Parallelism");
+                    let name = if let ParallelismTest::FTest { .. } = parallelism { "F-test" } else { "Equivalence" };
+                    ui.menu_button(name, |ui| {
+                        if ui.button("F-test").clicked() { *parallelism = ParallelismTest::FTest { alpha: 0.05 } }
+                        if ui.button("Equivalence").clicked() { *parallelism = ParallelismTest::Equivalence { lower: 0.8, upper: 1.25 } }
+                    });
+                    match parallelism {
+                        ParallelismTest::FTest { alpha } => {
+                            ui.add(DragValue::new(alpha).speed(0.001).range(0.001..=0.5).prefix("alpha "));
+                        }
+                        ParallelismTest::Equivalence { lower, upper } => {
+                            ui.add(DragValue::new(lower).speed(0.01).range(0.1..=1.0));
+                            ui.label("-");
+                            ui.add(DragValue::new(upper).speed(0.01).range(1.0..=10.0));
+                        }
+                    }
+                });
+
+                ui.add_space(10.0);
+                let LimitOptions { detection, quantification } = &mut options.limits;
+                Grid::new("Limits").num_columns(3).spacing(vec2(10.0, 5.0)).show(ui, |ui| {
+                    ui.label("LOD");
+                    let name = match detection { DetectionMethod::BlankSd { .. } => "Blank", DetectionMethod::ControlSd { .. } => "Control" };
+                    ui.menu_button(name, |ui| {
+                        if ui.button("Blank").clicked() { *detection = DetectionMethod::BlankSd { k: 3.0 } }
+                        if ui.button("Control").clicked() { *detection = DetectionMethod::ControlSd { k: 3.0 } }
+                    });
+                    let (DetectionMethod::BlankSd { k } | DetectionMethod::ControlSd { k }) = detection;
+                    ui.add(DragValue::new(k).speed(0.1).range(0.0..=20.0).prefix("+ ").suffix(" SD"));
+                    ui.end_row();
+
+                    ui.label("LOQ");
+                    let name = match quantification {
+                        QuantificationMethod::StandardRange => "Standards",
+                        QuantificationMethod::Precision { .. } => "Precision",
+                        QuantificationMethod::BlankSd { .. } => "Blank",
+                    };
+                    ui.menu_button(name, |ui| {
+                        if ui.button("Standards").clicked() { *quantification = QuantificationMethod::StandardRange }
+                        if ui.button("Precision").clicked() { *quantification = QuantificationMethod::Precision { recovery: 20.0, cv: 20.0 } }
+                        if ui.button("Blank").clicked() { *quantification = QuantificationMethod::BlankSd { k: 10.0 } }
+                    });
+                    match quantification {
+                        QuantificationMethod::StandardRange => (),
+                        QuantificationMethod::Precision { recovery, cv } => {
+                            ui.horizontal(|ui| {
+                                ui.add(DragValue::new(recovery).speed(0.5).range(0.0..=100.0).prefix("recovery +- ").suffix("%"));
+                                ui.add(DragValue::new(cv).speed(0.5).range(0.0..=100.0).prefix("CV < ").suffix("%"));
+                            });
+                        }
+                        QuantificationMethod::BlankSd { k } => {
+                            ui.add(DragValue::new(k).speed(0.1).range(0.0..=50.0).prefix("+ ").suffix(" SD"));
+                        }
+                    }
+                    ui.end_row();
+                });
+
+                ui.add_space(10.0);
+                ui.label("Acceptance");
+                Grid::new("Acceptance").num_columns(2).spacing(vec2(20.0, 5.0)).show(ui, |ui| {
+                    ui.label("Recovery");
+                    ui.add(DragValue::new(&mut acceptance.recovery).speed(0.5).range(0.0..=100.0).prefix("100 +- ").suffix("%"));
+                    ui.end_row();
+                    ui.label("Recovery at LLOQ");
+                    ui.add(DragValue::new(&mut acceptance.lloq_recovery).speed(0.5).range(0.0..=100.0).prefix("100 +- ").suffix("%"));
+                    ui.end_row();
+                    ui.label("Replicate CV");
+                    ui.add(DragValue::new(&mut acceptance.cv).speed(0.5).range(0.0..=100.0).prefix("< ").suffix("%"));
+                    ui.end_row();
+                    ui.label("R^2");
+                    ui.add(DragValue::new(&mut acceptance.r_squared).speed(0.001).range(0.0..=1.0).prefix(">= "));
+                    ui.end_row();
+                    ui.label("Passing standards");
+                    ui.add(DragValue::new(&mut acceptance.passing).speed(1.0).range(0.0..=100.0).prefix(">= ").suffix("%"));
+                    ui.end_row();
+                    let rule = &mut acceptance.quality_control;
+                    ui.label("Quality controls");
+                    ui.menu_button(rule.name(), |ui| {
+                        if ui.button("4-6-20").clicked() { *rule = QualityControlRule::FOUR_SIX_TWENTY }
+                        if ui.button("4-6-15").clicked() { *rule = QualityControlRule::FOUR_SIX_FIFTEEN }
+                    });
+                    ui.end_row();
+                    ui.label("");
+                    ui.horizontal(|ui| {
+                        ui.add(DragValue::new(&mut rule.passing).speed(1.0).range(0.0..=100.0).suffix("% of QCs"));
+                        ui.add(DragValue::new(&mut rule.per_level).speed(1.0).range(0.0..=100.0).suffix("% per level"));
+                        ui.add(DragValue::new(&mut rule.tolerance).speed(0.5).range(0.0..=100.0).prefix("+- ").suffix("%"));
+                    });
+                    ui.end_row();
+                });
+
+                ui.add_space(10.0);
+                ui.separator();
+                if ui.button("Ok").clicked() {
+                    self.fit_options_modal = false;
+                }
+            });
+        });
+    }
+
+    pub fn outlier_finder(&mut self, ui: &mut Ui) {
+        if !self.outlier_modal { return }
+        let microplate = &mut self.microplate;
+
+        egui::Modal::new(egui::Id::new("Outliers")).show(ui.ctx(), |ui| {
+            ui.vertical(|ui| {
+                ui.set_width(400.0);
+                ui.spacing_mut().item_spacing = vec2(10.0, 5.0);
+                ui.heading("Outliers");
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    let test = &mut self.outlier_test;
+                    ui.label("Test");
+                    ui.menu_button(test.name(), |ui| {
+                        for alternative in [OutlierTest::Grubbs, OutlierTest::DixonQ, OutlierTest::Rout] {
+                            if ui.button(alternative.name()).clicked() { *test = alternative }
+                        }
+                    });
+                    if ui.button("Find").clicked() {
+                        match suggest_outliers(microplate, self.outlier_test) {
+                            Ok(suggestions) => self.outlier_suggestions = Some(suggestions),
+                            Err(error) => self.value_error_modal = Some(error),
+                        }
+                    }
+                });
+
+                // Nothing is excluded until the user accepts it
+                ui.add_space(10.0);
+                if let Some(suggestions) = &mut self.outlier_suggestions {
+                    if suggestions.is_empty() {
+                        ui.label("No outliers found");
+                    }
+                    let mut decided = None;
+                    Grid::new("Suggestions").num_columns(4).spacing(vec2(10.0, 5.0)).show(ui, |ui| {
+                        for (i, Suggestion { well, reason }) in suggestions.iter().enumerate() {
+                            ui.label(microplate.well_name(*well));
+                            ui.label(reason);
+                            if ui.button("Accept").clicked() {
+                                microplate.samples[*well].excluded = Some(reason.clone());
+                                decided = Some(i);
+                            }
+                            if ui.button("Reject").clicked() {
+                                decided = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(i) = decided {
+                        suggestions.remove(i);
+                    }
+                }
+
+                ui.add_space(10.0);
+                ui.label("Excluded wells");
+                Grid::new("Excluded").num_columns(3).spacing(vec2(10.0, 5.0)).show(ui, |ui| {
+                    for i in 0..microplate.samples.len() {
+                        let Some(reason) = &microplate.samples[i].excluded else { continue };
+                        ui.label(microplate.well_name(i));
+                        ui.label(reason);
+                        if ui.button("Include").clicked() {
+                            microplate.samples[i].excluded = None;
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                ui.add_space(10.0);
+                ui.separator();
+                if ui.button("Ok").clicked() {
+                    self.outlier_modal = false;
+                    self.outlier_suggestions = None;
+                }
+            });
+        });
+    }
+
+    pub fn project_settings(&mut self, ui: &mut Ui) {
+        if !self.project_modal { return }
+        let mut switch = None;
+        let mut remove = None;
+
+        egui::Modal::new(egui::Id::new("Project")).show(ui.ctx(), |ui| {
+            ui.vertical(|ui| {
+                ui.set_width(350.0);
+                ui.spacing_mut().item_spacing = vec2(10.0, 5.0);
+                ui.heading("Project");
+                ui.add_space(10.0);
+
+                let project = &mut self.project;
+                Grid::new("Project metadata").num_columns(2).spacing(vec2(20.0, 5.0)).show(ui, |ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut project.name);
+                    ui.end_row();
+                    ui.label("Operator");
+                    ui.text_edit_singleline(&mut project.operator);
+                    ui.end_row();
+                    ui.label("Date");
+                    ui.text_edit_singleline(&mut project.date);
+                    ui.end_row();
+                });
+                ui.label("Description");
+                ui.add(TextEdit::multiline(&mut project.description).desired_rows(3));
+
+                ui.add_space(10.0);
+                ui.checkbox(&mut project.pooled, "Fit every plate to the pooled standards");
+
+                // The edited plate is renamed directly, the others in the project
+                ui.add_space(10.0);
+                ui.label("Plates");
+                Grid::new("Plates").num_columns(3).spacing(vec2(10.0, 5.0)).show(ui, |ui| {
+                    for i in 0..project.plates.len() {
+                        if ui.radio(i == self.current_plate, format!("{}", i + 1)).clicked() {
+                            switch = Some(i);
+                        }
+                        let name = if i == self.current_plate { &mut self.microplate.name } else { &mut project.plates[i].name };
+                        ui.text_edit_singleline(name);
+                        if project.plates.len() > 1 && ui.button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if ui.button("Add plate").clicked() {
+                    // A new plate keeps the format and the fit settings of the current one
+                    let Microplate { width, height, model, fit_options, unit, molecular_weight, acceptance, .. } = &self.microplate;
+                    project.plates.push(Microplate {
+                        model: *model,
+                        fit_options: fit_options.clone(),
+                        unit: *unit,
+                        molecular_weight: *molecular_weight,
+                        acceptance: *acceptance,
+                        ..Microplate::new(*width, *height)
+                    });
+                }
+
+                ui.add_space(10.0);
+                ui.separator();
+                if ui.button("Ok").clicked() {
+                    self.project_modal = false;
+                }
+            });
+        });
+
+        if let Some(i) = remove {
+            self.store_plate();
+            self.project.plates.remove(i);
+            self.plate_results.clear();
+            let current = if i < self.current_plate { self.current_plate - 1 } else { self.current_plate.min(self.project.plates.len() - 1) };
+            self.show_plate(current);
+        }
+        if let Some(i) = switch {
+            self.switch_plate(i);
+        }
+    }
+
+    pub fn template_library(&mut self, ui: &mut Ui) {
+        if !self.template_modal { return }
+        let microplate = &mut self.microplate;
+
+        egui::Modal::new(egui::Id::new("Templates")).show(ui.ctx(), |ui| {
+            ui.vertical(|ui| {
+                ui.set_width(350.0);
+                ui.spacing_mut().item_spacing = vec2(10.0, 5.0);
+                ui.heading("Templates");
+                ui.add_space(10.0);
+
+                // Applying keeps the measurements, only the layout and the groups change
+                if self.templates.is_empty() {
+                    ui.label("No templates saved yet");
+                }
+                let mut deleted = None;
+                Grid::new("Template list").num_columns(4).spacing(vec2(10.0, 5.0)).show(ui, |ui| {
+                    for (i, template) in self.templates.iter().enumerate() {
+                        ui.label(&template.name);
+                        ui.label(format!("{} wells", template.width * template.height));
+                        if ui.add_enabled(template.fits(microplate), Button::new("Apply")).clicked() {
+                            template.apply(microplate);
+                        }
+                        if ui.button("Delete").clicked() {
+                            match template.delete() {
+                                Ok(()) => deleted = Some(i),
+                                Err(_) => self.serde_error_modal = Some(SerdeError::CantWriteFile),
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = deleted {
+                    self.templates.remove(i);
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut self.template_name);
+                    let name = self.template_name.trim();
+                    if ui.add_enabled(!name.is_empty(), Button::new("Save layout")).clicked() {
+                        let template = Template::new(name.to_owned(), microplate);
+                        match template.save() {
+                            Ok(()) => self.templates = load_templates(),
+                            Err(_) => self.serde_error_modal = Some(SerdeError::CantWriteFile),
+                        }
+                    }
+                });
+
+                ui.add_space(10.0);
+                ui.separator();
+                if ui.button("Ok").clicked() {
+                    self.template_modal = false;
+                }
+            });
+        });
+    }
+
+    pub fn layout_wizard(&mut self, ui: &mut Ui) {
+        if !self.layout_modal { return }
+        let wizard = &mut self.layout_wizard;
+        let microplate = &mut self.microplate;
+
+        egui::Modal::new(egui::Id::new("Layout Wizard")).show(ui.ctx(), |ui| {
+            ui.vertical(|ui| {
+                ui.set_width(320.0);
+                ui.spacing_mut().item_spacing = vec2(10.0, 5.0);
+                ui.heading("Layout Wizard");
+                ui.add_space(10.0);
+
+                Grid::new("Layout").num_columns(2).spacing(vec2(20.0, 5.0)).show(ui, |ui| {
+                    ui.label("Standards");
+                    ui.add(DragValue::new(&mut wizard.standards).range(1..=100));
+                    ui.end_row();
+                    ui.label("Replicates");
+                    let name = match wizard.replicates {
+                        1 => String::from("Single"),
+                        2 => String::from("Duplicate"),
+                        3 => String::from("Triplicate"),
+                        n => format!("{n} replicates"),
+                    };
+                    ui.menu_button(name, |ui| {
+                        for (name, replicates) in [("Single", 1), ("Duplicate", 2), ("Triplicate", 3), ("4 replicates", 4)] {
+                            if ui.button(name).clicked() { wizard.replicates = replicates }
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("Orientation");
+                    ui.menu_button(wizard.orientation.name(), |ui| {
+                        for orientation in [Orientation::Vertical, Orientation::Horizontal] {
+                            if ui.button(orientation.name()).clicked() { wizard.orientation = orientation }
+                        }
+                    });
+                    ui.end_row();
+                    ui.label(format!("Top concentration{}", microplate.unit.suffix()));
+                    let speed = wizard.top_concentration * 0.01;
+                    ui.add(DragValue::new(&mut wizard.top_concentration).speed(speed).range(0.0..=f64::MAX));
+                    ui.end_row();
+                    // Presets for the common serial dilutions, any other factor can be dragged
+                    ui.label("Dilution");
+                    ui.horizontal(|ui| {
+                        for factor in [2.0, 3.0, 10.0] {
+                            ui.selectable_value(&mut wizard.dilution_factor, factor, format!("1:{factor}"));
+                        }
+                        ui.add(DragValue::new(&mut wizard.dilution_factor).speed(0.05).range(1.01..=1000.0).prefix("1:"));
+                    });
+                    ui.end_row();
+                    for (name, placement) in [("Blank", &mut wizard.blank), ("Control", &mut wizard.control)] {
+                        ui.label(name);
+                        ui.menu_button(placement.name(), |ui| {
+                            for alternative in [Placement::None, Placement::AfterStandards, Placement::EndOfPlate] {
+                                if ui.button(alternative.name()).clicked() { *placement = alternative }
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+                ui.checkbox(&mut wizard.unknowns, "Fill the rest of the plate with unknowns");
+
+                ui.add_space(10.0);
+                let (needed, slots) = (wizard.slots_needed(), wizard.slots(microplate));
+                let fits = needed <= slots;
+                let text = format!("{} of {} wells for standards, blank and control", needed * wizard.replicates, slots * wizard.replicates);
+                ui.label(if fits { RichText::new(text) } else { RichText::new(text).color(Color32::from_hex("#D03030").unwrap()) });
+
+                ui.add_space(10.0);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(fits, Button::new("Apply")).clicked() && wizard.apply(microplate) {
+                        self.selected_samples.clear();
+                        self.layout_modal = false;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.layout_modal = false;
+                    }
+                });
+            });
+        });
+    }
+
+    pub fn import_dialog(&mut self, ui: &mut Ui) {
+        if !self.import_modal { return }
+        let (width, height) = (self.microplate.width, self.microplate.height);
+        let red = Color32::from_hex("#D03030").unwrap();
+        let mut sheet_choice = None;
+        let mut profile_choice = None;
+        let (mut detect, mut import) = (false, false);
+
+        egui::Modal::new(egui::Id::new("Import")).show(ui.ctx(), |ui| {
+            ui.vertical(|ui| {
+                ui.set_width(560.0);
+                ui.spacing_mut().item_spacing = vec2(10.0, 5.0);
+                ui.heading("Import");
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    ui.label("Sheet");
+                    let name = self.sheet_names.get(self.selected_sheet).cloned().unwrap_or_default();
+                    ui.menu_button(name, |ui| {
+                        for (i, sheet) in self.sheet_names.iter().enumerate() {
+                            if ui.button(sheet).clicked() { sheet_choice = Some(i) }
+                        }
+                    });
+                    ui.add_space(10.0);
+                    ui.label("Range");
+                    ui.add(TextEdit::singleline(&mut self.import_range).desired_width(100.0));
+                    if ui.button("Detect").clicked() {
+                        detect = true;
+                    }
+                });
+                if let Some(format) = self.import_info.format {
+                    let fields: Vec<_> = self.import_info.fields().into_iter().skip(1).map(|(label, value)| format!("{label}: {value}")).collect();
+                    ui.label(format!("{} export. {}", format.name(), fields.join(", ")));
+                }
+
+                // Clicking a cell takes it as the top-left well of a block the size of the plate
+                let range = CellRange::parse(&self.import_range);
+                let mut corner = None;
+                ui.add_space(5.0);
+                if let Some(sheet) = &self.import_sheet {
+                    let (rows, columns) = sheet.end().map_or((0, 0), |(row, column)| (row as usize + 1, (column as usize + 1).min(64)));
+                    ScrollArea::horizontal().id_salt("Preview").show(ui, |ui| {
+                        TableBuilder::new(ui)
+                            .max_scroll_height(250.0)
+                            .column(Column::exact(30.0))
+                            .columns(Column::exact(60.0).clip(true), columns)
+                            .header(18.0, |mut header| {
+                                header.col(|_| {});
+                                for column in 0..columns {
+                                    header.col(|ui| { ui.strong(column_name(column)); });
+                                }
+                            })
+                            .body(|body| {
+                                body.rows(18.0, rows, |mut table_row| {
+                                    let row = table_row.index();
+                                    table_row.col(|ui| { ui.strong((row + 1).to_string()); });
+                                    for column in 0..columns {
+                                        table_row.col(|ui| {
+                                            let selected = range.is_some_and(|range| range.contains((row, column)));
+                                            if ui.selectable_label(selected, cell_text(sheet, (row, column))).clicked() {
+                                                corner = Some((row, column));
+                                            }
+                                        });
+                                    }
+                                });
+                            });
+                    });
+                } else {
+                    ui.label("The sheet could not be read");
+                }
+                if let Some(corner) = corner {
+                    self.import_range = CellRange::from_corner(corner, width, height).address();
+                }
+
+                let fits = self.import_fits();
+                ui.add_space(5.0);
+                match range {
+                    None => ui.label(RichText::new("Enter a range like B38:M45 or click the top-left well").color(red)),
+                    Some(range) if !fits => ui.label(RichText::new(format!("{} × {} cells do not fit on the plate of {} × {} wells", range.height(), range.width(), height, width)).color(red)),
+                    Some(range) => ui.label(format!("{} × {} cells", range.height(), range.width())),
+                };
+
+                // Profiles remember where one reader puts its measurements
+                ui.add_space(10.0);
+                ui.strong("Profiles");
+                let mut deleted = None;
+                Grid::new("Import profiles").num_columns(4).spacing(vec2(10.0, 5.0)).show(ui, |ui| {
+                    for (i, profile) in self.import_profiles.iter().enumerate() {
+                        ui.label(&profile.name);
+                        ui.label(profile.range.map_or(String::from("Detect"), |range| range.address()));
+                        if ui.button("Apply").clicked() {
+                            profile_choice = Some(profile.clone());
+                        }
+                        if ui.button("Delete").clicked() {
+                            deleted = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = deleted {
+                    self.import_profiles.remove(i);
+                    if save_profiles(&self.import_profiles).is_err() {
+                        self.serde_error_modal = Some(SerdeError::CantWriteFile);
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut self.import_profile_name);
+                    let name = self.import_profile_name.trim();
+                    if ui.add_enabled(!name.is_empty(), Button::new("Save profile")).clicked() {
+                        let profile = ImportProfile { name: name.to_owned(), sheet: self.selected_sheet, range };
+                        self.import_profiles.retain(|other| other.name != profile.name);
+                        self.import_profiles.push(profile);
+                        self.import_profiles.sort_by(|a, b| a.name.cmp(&b.name));
+                        if save_profiles(&self.import_profiles).is_err() {
+                            self.serde_error_modal = Some(SerdeError::CantWriteFile);
+                        }
+                    }
+                });
+
+                ui.add_space(10.0);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(fits, Button::new("Import")).clicked() {
+                        import = true;
+                        self.import_modal = false;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.import_modal = false;
+                    }
+                });
+            });
+        });
+
+        if let Some(i) = sheet_choice {
+            self.select_sheet(i);
+        }
+        if detect {
+            self.detect_import();
+        }
+        if import {
+            self.import();
+        }
+        if let Some(profile) = profile_choice {
+            if profile.sheet < self.sheet_names.len() {
+                self.select_sheet(profile.sheet);
+            }
+            if let Some(range) = profile.range {
+                self.import_range = range.address();
+            }
+        }
+    }
+
+    // Opens an Excel or text export. Exports of known readers that fit the plate are imported right away,
+    // anything else goes through the import dialog.
+    fn open_export(&mut self, path: PathBuf) {
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("xlsx")) {
+            match open_workbook::<Xlsx<_>, PathBuf>(path) {
+                Ok(xlsx) => {
+                    self.sheet_names = xlsx.sheet_names();
+                    self.excel = Some(xlsx);
+                }
+                Err(_) => {
+                    self.serde_error_modal = Some(SerdeError::CantReadFile);
+                    return
+                }
+            }
+            // The first sheet a reader is recognized on, or else the first one
+            let known = (0..self.sheet_names.len()).find(|&i| {
+                self.select_sheet(i);
+                self.import_info.format.is_some()
+            });
+            if known.is_none() { self.select_sheet(0) }
+        } else {
+            match std::fs::read(&path) {
+                Ok(bytes) => {
+                    self.sheet_names = vec![path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()];
+                    self.excel = None;
+                    self.selected_sheet = 0;
+                    self.import_sheet = Some(text_sheet(&bytes));
+                    self.detect_import();
+                }
+                Err(error) => {
+                    self.serde_error_modal = Some(if error.kind() == std::io::ErrorKind::NotFound { SerdeError::FileNotFound } else { SerdeError::CantReadFile });
+                    return
+                }
+            }
+        }
+
+        if self.import_info.format.is_some() && self.import_fits() {
+            self.import();
+        } else {
+            self.import_profiles = load_profiles();
+            self.import_modal = true;
+        }
+    }
+
+    // Reads the sheet once and searches it for a plate
+    fn select_sheet(&mut self, index: usize) {
+        let Some(excel) = &mut self.excel else { return };
+        self.selected_sheet = index;
+        self.import_sheet = match excel.worksheet_range_at(index) {
+            Some(Ok(sheet)) => Some(sheet),
+            Some(Err(_)) => {
+                self.serde_error_modal = Some(SerdeError::CantReadSheet);
+                None
+            },
+            None => None,
+        };
+        self.detect_import();
+    }
+
+    // The measurements and details of a known reader, or else the block under the headers of a plate
+    fn detect_import(&mut self) {
+        let (range, info) = match &self.import_sheet {
+            Some(sheet) => match read_export(sheet) {
+                Some((range, info)) => (Some(range), info),
+                None => (detect_plate(sheet), default()),
+            },
+            None => (None, default()),
+        };
+        self.import_range = range.map(|range| range.address()).unwrap_or_default();
+        self.import_info = info;
+    }
+
+    fn import_fits(&self) -> bool {
+        CellRange::parse(&self.import_range).is_some_and(|range| range.width() <= self.microplate.width && range.height() <= self.microplate.height)
+    }
+
+    fn import(&mut self) {
+        let (Some(sheet), Some(range)) = (&self.import_sheet, CellRange::parse(&self.import_range)) else { return };
+        self.data_textfield = Self::data_to_string(read_range(sheet, range));
+        self.microplate.read_info = self.import_info.clone();
+    }
+
     fn string_to_data(data: &str, width: usize, height: usize) -> Result<Vec<Vec<Option<f64>>>, StringToDataError> {
         use StringToDataError::*;
         let mut result = Vec::new();
@@ -601,31 +1685,6 @@ impl Elisa {
         println!("{}", result);
         result
     }
-    
-    fn parse_xlsx_sheet(excel: &mut Xlsx<BufReader<File>>, sheet: usize) -> Result<Vec<Vec<Option<f64>>>, ParseExcelError> {
-        use ParseExcelError::*;
-
-        let data = excel.worksheet_range_at_ref(sheet).unwrap()?;
-        if data.get_size() < (65, 8) {
-            return Err(SheetSize)
-        }
-        let Some(mut table_dimensions) = data[(25, 4)].as_string() else {
-            return Err(NoDimensions)
-        };
-        table_dimensions.retain(|char| char.is_ascii_uppercase());
-        let table_height = table_dimensions.chars().max().unwrap_or('A'); // maybe replace unwrap_or(...) with else { return Err(...)}?
-        let table_height = (u32::from(table_height) - u32::from('A') + 1) as usize;
-        let result: Vec<Vec<Option<f64>>> = data.rows()
-            .skip(37 + 2 * table_height)
-            .take(table_height)
-            .map(|row| 
-                row.iter()
-                    .skip(1)
-                    .map(|cell| cell.get_float())
-                    .collect()
-            ).collect();
-        Ok(result)
-    }
 }
 
 // Hmmm... maybe I should use thiserror
@@ -655,26 +1714,23 @@ impl Display for StringToDataError {
     }
 }
 
-#[derive(Debug)]
-enum ParseExcelError {
-    SheetSize,
-    NoDimensions,
-    XlsxError(XlsxError),
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl From<XlsxError> for ParseExcelError {
-    fn from(value: XlsxError) -> Self {
-        ParseExcelError::XlsxError(value)
-    }
-}
+    // A 1536-well plate can have far more standard groups than a 96-well one
+    #[test]
+    fn standards_table_has_a_row_for_every_group() {
+        let mut elisa = Elisa { microplate: Microplate::new(48, 32), ..default() };
+        let sample = &mut elisa.microplate.samples[0];
+        (sample.typ, sample.group) = (SampleType::Standard, 149);
+        elisa.microplate.resize_groups();
+        elisa.microplate.standard_groups[0].concentration = Some(1000.0);
 
-impl Display for ParseExcelError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let error = match self {
-            Self::SheetSize => String::from("Sheet size is too small"),
-            Self::NoDimensions => String::from("Could not parse table dimensions"),
-            Self::XlsxError(value) => format!("{}", value)
-        };
-        write!(f, "{}", error)
+        let _ = egui::Context::default().run(default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| elisa.standards_concentrations(ui));
+        });
+        assert_eq!(elisa.standards_textfield.len(), 150);
+        assert_eq!(elisa.standards_textfield[0], "1000");
     }
 }
@@ -1,26 +1,27 @@
 use std::path::PathBuf;
 
-use eframe::egui::{self, vec2, Color32, Label, RichText, Ui, UserData};
+use eframe::egui::{self, vec2, Color32, Label, RichText, ScrollArea, Ui, UserData};
 use egui_extras::{Column, TableBuilder};
-use egui_plot::{AxisTransforms, Line, Plot, PlotPoint, PlotPoints, Points, Text};
+use egui_plot::{AxisTransforms, Line, LineStyle, Plot, PlotPoint, PlotPoints, Points, Text};
 use image::{ImageBuffer, Pixel, Rgba, RgbaImage};
 use pdf_writer::{Content, Finish, Name, Pdf, Ref, Str, TextStr};
 
-use crate::{logistic_regression::*, Elisa};
+use crate::{acceptance::*, limits::*, logistic_regression::*, model_comparison::*, potency::*, project::*, Elisa};
 
 impl Elisa {
     pub fn plot(&mut self, ui: &mut Ui) {
         let Some(regression) = self.regression.as_ref() else { return };
-        let Regression { abcd, unknowns, standards, ..} = regression;
+        let Regression { model, unknowns, standards, standard_deviations, replicates, limits, potencies, unit, acceptance, ..} = regression;
+        let low = standards.iter().map(|&(_x, y)| y).fold(f64::MAX, f64::min);
+        let high = standards.iter().map(|&(_x, y)| y).fold(f64::MIN, f64::max);
+        let first = standards.first().map_or(0.0, |&(x, _y)| x);
+        let last = standards.last().map_or(0.0, |&(x, _y)| x);
 
-        let &(a, b, c, d) = abcd;
-        
         let stroke = ui.visuals().noninteractive().bg_stroke;
         let color = ui.style().noninteractive().text_color();
 
-        let four_pl = move |x: f64| {
-            d + ((a - d) / (1.0 + (x/c).powf(b)))
-        };
+        let curve = regression.clone();
+        let curve = move |x: f64| curve.evaluate(x);
 
         let axis_transforms = AxisTransforms::new(egui_plot::AxisTransform::Logarithmic(10.0), egui_plot::AxisTransform::Linear);
         
@@ -29,23 +30,57 @@ impl Elisa {
             .show_x(false)
             .show_y(false)
             .axis_transforms(axis_transforms)
-            .x_axis_label("Dose")
+            .x_axis_label(format!("Dose{}", unit.suffix()))
             .y_axis_label("Measurement")
             .show_background(false)
             .height(500.0)
             .width(500.0)
             .show(ui, |ui| {
-            // Curve
-            let line_points = PlotPoints::from_explicit_callback(four_pl, .., 5000);
+            // Curve, interpolation is only drawn between the standards
+            let line_points = if model.interpolates() {
+                PlotPoints::from_explicit_callback(curve, first..=last, 5000)
+            } else {
+                PlotPoints::from_explicit_callback(curve, .., 5000)
+            };
             let line = Line::new(line_points)
                 .allow_hover(false)
                 .color(color)
-                .name("4PL");
+                .name(model.name());
             ui.line(line);
+
+            // Limits of detection and quantification
+            for (name, x) in [("LOD", limits.lod), ("LLOQ", limits.lloq), ("ULOQ", limits.uloq)] {
+                if !x.is_finite() || x <= 0.0 { continue }
+                let line = Line::new(vec![[x, low], [x, high]])
+                    .allow_hover(false)
+                    .color(color.gamma_multiply(0.5))
+                    .style(LineStyle::dashed_dense())
+                    .name(name);
+                ui.line(line);
+                ui.text(Text::new(PlotPoint::new(x, high), RichText::new(name).size(11.0)).anchor(egui::Align2::CENTER_BOTTOM));
+            }
         
-            // Standards points
-            for &(dose, value) in standards {
-                let color = SampleType::Standard.color();
+            // Standards points, with the replicates and mean +- SD error bars
+            let color = SampleType::Standard.color();
+            for &(dose, value) in replicates {
+                let point = Points::new([dose, value])
+                    .radius(2.0)
+                    .color(color.gamma_multiply(0.6));
+                ui.points(point);
+            }
+            let red = Color32::from_hex("#D03030").unwrap();
+            for (check, &(dose, value)) in acceptance.standards.iter().zip(standards) {
+                if !check.passed {
+                    ui.points(Points::new([dose, value]).radius(8.0).color(red).name("Failed acceptance"));
+                }
+            }
+            for (&(dose, value), &sd) in standards.iter().zip(standard_deviations) {
+                if sd > 0.0 {
+                    let error_bar = Line::new(vec![[dose, value - sd], [dose, value + sd]])
+                        .allow_hover(false)
+                        .color(color);
+                    ui.line(error_bar);
+                }
                 let point = Points::new([dose, value])
                     .radius(5.0)
                     .color(color);
@@ -55,13 +90,35 @@ impl Elisa {
             // Unknowns points
             let white = Color32::from_hex("#FBFBFE").unwrap();
             let color = SampleType::Unknown.color();
-            for (i, (dose, value, label)) in unknowns.iter().enumerate() {
+            for (i, unknown) in unknowns.iter().enumerate() {
+                let Unknown { label, raw: value, backfit: dose, replicates, backfits, confidence_interval: (low, high), .. } = unknown;
                 let name = if label.is_empty() {
                     format!("Unknown {}", i + 1)
                 } else {
                     label.to_owned()
                 };
 
+                for (&dose, &value) in backfits.iter().zip(replicates) {
+                    let point = Points::new([dose, value])
+                        .radius(2.0)
+                        .color(color.gamma_multiply(0.6));
+                    ui.points(point);
+                }
+                let (_, sd) = mean_and_sd(replicates);
+                if sd > 0.0 {
+                    let error_bar = Line::new(vec![[*dose, value - sd], [*dose, value + sd]])
+                        .allow_hover(false)
+                        .color(color);
+                    ui.line(error_bar);
+                }
+                // 95% confidence interval of the backfit
+                if low.is_finite() && high.is_finite() {
+                    let error_bar = Line::new(vec![[*low, *value], [*high, *value]])
+                        .allow_hover(false)
+                        .color(color);
+                    ui.line(error_bar);
+                }
+
                 let point = Points::new([*dose, *value])
                     .name(name.clone())
                     .radius(5.0)
@@ -77,6 +134,26 @@ impl Elisa {
                     RichText::new(name.clone()).size(11.0).background_color(white.gamma_multiply(0.7))
                 ));
             }
+
+            // Dilution series of the test samples at their dose, with the curve parallel to the standards
+            for Potency { label, points, curve, .. } in potencies {
+                let curve = curve.clone();
+                let g = curve.get(4).copied().unwrap_or(1.0);
+                let line_points = PlotPoints::from_explicit_callback(
+                    move |x: f64| log_logistic(x.ln(), curve[0], curve[1], curve[2], curve[3], g, &mut [0.0; 5]),
+                    ..,
+                    5000
+                );
+                let line = Line::new(line_points)
+                    .allow_hover(false)
+                    .color(color)
+                    .style(LineStyle::dashed_dense())
+                    .name(label);
+                ui.line(line);
+                for &(dose, value) in points {
+                    ui.points(Points::new([dose, value]).radius(3.0).color(color));
+                }
+            }
         });
         ui.painter().rect_stroke(plot.response.rect, 0.0, stroke, eframe::egui::StrokeKind::Inside);
         plot.response.rect = plot.response.rect.expand(10.0);
@@ -87,8 +164,13 @@ impl Elisa {
 
     pub fn plot_parameters(&mut self, ui: &mut Ui) -> Option<()> {
         let regression = self.regression.as_ref()?;
-        let &Regression { abcd, mse, sse, sy_x, rmse, r_sq,  ..} = regression;
-        let (a, b, c, d) = abcd;
+        let &Regression { model, mse, sse, sy_x, rmse, r_sq, convergence, limits, ..} = regression;
+        let LimitOptions { detection, quantification } = regression.options.limits;
+        let multi_start = regression.multi_start.as_ref().map(|report| report.description());
+        let weighting = regression.weighting_description();
+        let direction = regression.direction_description();
+        let excluded: Vec<_> = regression.excluded.iter().map(|(well, _reason)| well.as_str()).collect();
+        let excluded = excluded.join(", ");
 
         let background = ui.visuals().faint_bg_color;
         let stroke = ui.visuals().noninteractive().bg_stroke;
@@ -97,16 +179,59 @@ impl Elisa {
         // let sse = regression.sum_of_squares();
         // let sy_x = regression.sy_x();
         // let rmse = regression.root_mean_squared_error();
-        let list = [("a", a), ("b", b), ("c", c), ("d", d), ("MSE", mse), ("SSE", sse), ("Sy.x", sy_x), ("RMSE", rmse), ("R^2", r_sq)];
+        let mut list = regression.parameter_values();
+        list.extend([("MSE", mse), ("SSE", sse), ("Sy.x", sy_x), ("RMSE", rmse), ("R^2", r_sq)]);
+        list.extend([("LOD", limits.lod), ("LLOQ", limits.lloq), ("ULOQ", limits.uloq)]);
+        let standard_errors = regression.standard_errors.clone();
+        let confidence_intervals = regression.confidence_intervals.clone();
+        let interval_method = regression.options.intervals.name();
+        let bootstrap = self.bootstrap.as_ref().map(|job| job.description());
 
-        self.plot_parameters = Some(list);
+        self.plot_parameters = Some(list.clone());
 
         egui::Frame::new().show(ui, |ui| {
             let width = ui.available_width().max(20.0);
             ui.set_width(width);
 
-            ui.vertical_centered(|ui| ui.heading("Parameters"));
-            ui.add_space(10.0);
+            ui.vertical_centered(|ui| ui.heading(format!("Parameters - {}", model.name())));
+            ui.add_space(5.0);
+            ui.vertical_centered(|ui| {
+                let text = RichText::new(convergence.description()).size(11.0);
+                // A fit that did not converge should not go unnoticed
+                if convergence.is_converged() {
+                    ui.label(text);
+                } else {
+                    ui.label(text.color(Color32::from_hex("#D03030").unwrap()));
+                }
+                if let Some(multi_start) = &multi_start {
+                    ui.label(RichText::new(multi_start).size(11.0));
+                }
+                ui.label(RichText::new(&direction).size(11.0));
+                ui.label(RichText::new(&weighting).size(11.0));
+                ui.label(RichText::new(format!("95% CI: {interval_method}")).size(11.0));
+                if let Some(bootstrap) = &bootstrap {
+                    ui.label(RichText::new(bootstrap).size(11.0));
+                }
+                ui.label(RichText::new(format!("LOD: {}, LOQ: {}", detection.name(), quantification.name())).size(11.0));
+                if !excluded.is_empty() {
+                    ui.label(RichText::new(format!("Excluded: {excluded}")).size(11.0));
+                }
+                // Tab separated, to paste the values with their uncertainty into a spreadsheet
+                let copy = ui.button("Copy table");
+                Self::dashed_outline(ui, &copy);
+                if copy.clicked() {
+                    let mut text = String::from("\tValue\tSE\t95% CI low\t95% CI high\n");
+                    for (index, (name, value)) in list.iter().enumerate() {
+                        text += &format!("{name}\t{value}");
+                        if let (Some(se), Some((low, high))) = (standard_errors.get(index), confidence_intervals.get(index)) {
+                            text += &format!("\t{se}\t{low}\t{high}");
+                        }
+                        text.push('\n');
+                    }
+                    ui.ctx().copy_text(text);
+                }
+            });
+            ui.add_space(5.0);
             egui::Frame::new()
                 .fill(background).stroke(stroke)
                 .inner_margin(10.0)
@@ -114,25 +239,37 @@ impl Elisa {
                     ui.set_width(width - 20.0);
                     ui.spacing_mut().item_spacing = vec2(20.0, 5.0);
 
-                    TableBuilder::new(ui).id_salt("Plot parameters")
-                        // .max_scroll_height(100.0)
-                        .min_scrolled_height(150.0)
-                        .column(Column::auto())
-                        .column(Column::remainder())
-                        .body(|body| {
-                            body.rows(20.0, list.len(), |mut row| {
-                                let index = row.index();
-                                row.col(|ui| { ui.add(Label::new(list[index].0).selectable(true)); });
-                                row.col(|ui| { ui.add(Label::new(format!("{}", list[index].1)).selectable(true)); });
+                    ScrollArea::horizontal().id_salt("Plot parameters scroll").show(ui, |ui| {
+                        TableBuilder::new(ui).id_salt("Plot parameters")
+                            // .max_scroll_height(100.0)
+                            .min_scrolled_height(150.0)
+                            .columns(Column::auto(), 3)
+                            .column(Column::remainder())
+                            .header(20.0, |mut header| {
+                                for name in ["", "Value", "SE", "95% CI"] {
+                                    header.col(|ui| { ui.add(Label::new(name).selectable(true)); });
+                                }
+                            })
+                            .body(|body| {
+                                body.rows(20.0, list.len(), |mut row| {
+                                    let index = row.index();
+                                    row.col(|ui| { ui.add(Label::new(list[index].0).selectable(true)); });
+                                    row.col(|ui| { ui.add(Label::new(format!("{}", list[index].1)).selectable(true)); });
+                                    // Only the curve parameters have an uncertainty
+                                    if let (Some(se), Some((low, high))) = (standard_errors.get(index), confidence_intervals.get(index)) {
+                                        row.col(|ui| { ui.add(Label::new(format!("{se:.4}")).selectable(true)); });
+                                        row.col(|ui| { ui.add(Label::new(format!("[{low:.4}, {high:.4}]")).selectable(true)); });
+                                    }
+                                });
                             });
-                        });
+                    });
                 });
         });
         Some(())
     }
 
     pub fn backfit_concentrations(&self, ui: &mut Ui) {
-        let Some(Regression { unknowns, .. }) = &self.regression else { return };
+        let Some(Regression { unknowns, dilution_series, unit, .. }) = &self.regression else { return };
         
         let background = ui.visuals().faint_bg_color;
         let stroke = ui.visuals().noninteractive().bg_stroke;
@@ -141,8 +278,14 @@ impl Elisa {
             let width = ui.available_width().max(20.0);
             ui.set_width(width);
 
-            ui.vertical_centered(|ui| ui.heading("Backfit Concentrations"));
-            ui.add_space(10.0);
+            ui.vertical_centered(|ui| ui.heading(format!("Backfit Concentrations{}", unit.suffix())));
+            ui.add_space(5.0);
+            ui.vertical_centered(|ui| {
+                for series in dilution_series {
+                    ui.label(RichText::new(series.description()).size(11.0));
+                }
+            });
+            ui.add_space(5.0);
             egui::Frame::new()
                 .fill(background).stroke(stroke)
                 .inner_margin(10.0)
@@ -152,30 +295,320 @@ impl Elisa {
                     ui.set_width(width - 20.0);
                     ui.spacing_mut().item_spacing = vec2(20.0, 0.0);
 
+                    ScrollArea::horizontal().id_salt("Backfit Concentrations Scroll").show(ui, |ui| {
+                        TableBuilder::new(ui)
+                            .id_salt("Backfit Concentrations")
+                            .min_scrolled_height(height - 20.0)
+                            .max_scroll_height(height - 20.0)
+                            .columns(Column::auto(), 11)
+                            .column(Column::remainder())
+                            .header(20.0, |mut header| {
+                                for name in ["Group", "Raw Corrected", "Backfit", "Dilution", "Final", "Mean", "SD", "%CV", "CI Low", "CI High", "PI Low", "PI High"] {
+                                    header.col(|ui| { ui.add(Label::new(name).selectable(true)); });
+                                }
+                            })
+                            .body(|body| {
+                                body.rows(25.0, unknowns.len(), |mut row| {
+                                    let index = row.index();
+                                    let Unknown { label, raw, backfit, mean, sd, cv, flag, dilution, concentration, .. } = &unknowns[index];
+                                    let (confidence_interval, prediction_interval) = unknowns[index].final_intervals();
+
+                                    row.col(|ui| { ui.add(Label::new(label).selectable(true)); });
+                                    let mut raw = raw.to_string();
+                                    raw.truncate(10);
+                                    row.col(|ui| { ui.add(Label::new(raw).selectable(true)); });
+                                    // Concentrations outside the working range are not reportable
+                                    if let Some(flag) = flag {
+                                        row.col(|ui| { ui.add(Label::new(RichText::new(flag.label()).color(Color32::from_hex("#D03030").unwrap())).selectable(true)); });
+                                        return
+                                    }
+                                    for value in [backfit, dilution, concentration, mean, sd, cv, &confidence_interval.0, &confidence_interval.1, &prediction_interval.0, &prediction_interval.1] {
+                                        let mut value = value.to_string();
+                                        value.truncate(10);
+                                        row.col(|ui| { ui.add(Label::new(value).selectable(true)); });
+                                    }
+                                });
+                            });
+                    });
+                });
+        });
+    }
+
+    /// Final concentrations of every plate in the project
+    pub fn combined_results(&self, ui: &mut Ui) {
+        let red = Color32::from_hex("#D03030").unwrap();
+        let mut rows = Vec::new();
+        for (i, result) in self.plate_results.iter().enumerate() {
+            let plate = self.project.plate_name(i);
+            let Ok(regression) = result else {
+                rows.push((plate, String::new(), RichText::new("Could not be fitted").color(red), String::new()));
+                continue
+            };
+            for unknown in &regression.unknowns {
+                let text = match unknown.flag {
+                    Some(flag) => RichText::new(flag.label()).color(red),
+                    None => RichText::new(format!("{:.4}", unknown.concentration)),
+                };
+                rows.push((plate.clone(), unknown.label.clone(), text, format!("{:.1}", unknown.cv)));
+            }
+            let verdict = regression.acceptance.verdict.description();
+            rows.push((plate, String::new(), RichText::new(verdict).size(11.0), String::new()));
+        }
+
+        let background = ui.visuals().faint_bg_color;
+        let stroke = ui.visuals().noninteractive().bg_stroke;
+        let pooled = if self.project.pooled { ", pooled standards" } else { "" };
+
+        ui.vertical(|ui| {
+            ui.set_width(500.0);
+            ui.vertical_centered(|ui| ui.heading(format!("All Plates{pooled}")));
+            ui.add_space(10.0);
+            egui::Frame::new()
+                .fill(background).stroke(stroke)
+                .inner_margin(10.0)
+                .show(ui, |ui| {
+                    ui.set_width(480.0);
+                    TableBuilder::new(ui)
+                        .id_salt("All Plates")
+                        .max_scroll_height(200.0)
+                        .columns(Column::auto(), 3)
+                        .column(Column::remainder())
+                        .header(20.0, |mut header| {
+                            for name in ["Plate", "Sample", "Final", "%CV"] {
+                                header.col(|ui| { ui.add(Label::new(name).selectable(true)); });
+                            }
+                        })
+                        .body(|body| {
+                            body.rows(25.0, rows.len(), |mut row| {
+                                let (plate, label, text, cv) = &rows[row.index()];
+                                row.col(|ui| { ui.add(Label::new(plate).selectable(true)); });
+                                row.col(|ui| { ui.add(Label::new(label).selectable(true)); });
+                                row.col(|ui| { ui.add(Label::new(text.clone()).selectable(true)); });
+                                row.col(|ui| { ui.add(Label::new(cv).selectable(true)); });
+                            });
+                        });
+                });
+        });
+    }
+
+    pub fn model_comparison(&mut self, ui: &mut Ui) {
+        let Some(regression) = &self.regression else { return };
+        let ModelComparison { candidates, f_tests, recommended } = regression.comparison.clone();
+        let current = regression.model;
+        let best_aicc = candidates.iter().map(|candidate| candidate.aicc).fold(f64::INFINITY, f64::min);
+
+        let background = ui.visuals().faint_bg_color;
+        let stroke = ui.visuals().noninteractive().bg_stroke;
+        let mut selected = None;
+
+        egui::Frame::new().show(ui, |ui| {
+            ui.set_width(500.0);
+            ui.vertical_centered(|ui| ui.heading("Model Comparison"));
+            ui.add_space(5.0);
+            ui.vertical_centered(|ui| {
+                let text = match recommended {
+                    Some(model) => format!("Recommended: {} (lowest AICc)", model.name()),
+                    None => String::from("No model converged"),
+                };
+                ui.label(RichText::new(text).size(11.0));
+                for FTest { simpler, complex, f, p_value } in &f_tests {
+                    let text = format!("{} vs {}: F = {f:.3}, p = {p_value:.4}", complex.name(), simpler.name());
+                    ui.label(RichText::new(text).size(11.0));
+                }
+            });
+            ui.add_space(5.0);
+            egui::Frame::new()
+                .fill(background).stroke(stroke)
+                .inner_margin(10.0)
+                .show(ui, |ui| {
+                    ui.set_width(480.0);
+                    ui.spacing_mut().item_spacing = vec2(20.0, 0.0);
+
+                    TableBuilder::new(ui)
+                        .id_salt("Model Comparison")
+                        .columns(Column::auto(), 6)
+                        .column(Column::remainder())
+                        .header(20.0, |mut header| {
+                            for name in ["Model", "SSE", "Sy.x", "AICc", "Delta AICc", "BIC", ""] {
+                                header.col(|ui| { ui.add(Label::new(name).selectable(true)); });
+                            }
+                        })
+                        .body(|body| {
+                            body.rows(25.0, candidates.len(), |mut row| {
+                                let Candidate { model, sse, sy_x, aicc, bic, converged } = candidates[row.index()];
+                                row.col(|ui| {
+                                    let text = RichText::new(model.name());
+                                    let text = if converged { text } else { text.color(Color32::from_hex("#D03030").unwrap()) };
+                                    ui.add(Label::new(text).selectable(true));
+                                });
+                                for value in [sse, sy_x, aicc, aicc - best_aicc, bic] {
+                                    row.col(|ui| { ui.add(Label::new(format!("{value:.4}")).selectable(true)); });
+                                }
+                                row.col(|ui| {
+                                    if model == current {
+                                        ui.label("In use");
+                                    } else {
+                                        let button = ui.button("Use");
+                                        Self::dashed_outline(ui, &button);
+                                        if button.clicked() { selected = Some(model) }
+                                    }
+                                });
+                            });
+                        });
+                });
+        });
+
+        // Refit the plate with the chosen model
+        if let Some(model) = selected {
+            self.microplate.model = model;
+            // The plate keeps its last result if it cannot be fitted with the model
+            if let Err(error) = self.calculate() {
+                self.value_error_modal = Some(error);
+            }
+        }
+    }
+
+    pub fn standards_acceptance(&self, ui: &mut Ui) {
+        let Some(regression) = &self.regression else { return };
+        let Acceptance { criteria, standards, quality_controls, verdict } = &regression.acceptance;
+
+        let background = ui.visuals().faint_bg_color;
+        let stroke = ui.visuals().noninteractive().bg_stroke;
+        let red = Color32::from_hex("#D03030").unwrap();
+
+        egui::Frame::new().show(ui, |ui| {
+            ui.set_width(500.0);
+            ui.vertical_centered(|ui| ui.heading("Run Acceptance"));
+            ui.add_space(5.0);
+            ui.vertical_centered(|ui| {
+                let text = RichText::new(verdict.description()).size(11.0);
+                ui.label(if verdict.passed { text } else { text.color(red) });
+                ui.label(RichText::new(criteria.description()).size(11.0));
+                if !quality_controls.is_empty() {
+                    ui.label(RichText::new(format!("Quality controls: {}", criteria.quality_control.name())).size(11.0));
+                }
+            });
+            ui.add_space(5.0);
+            egui::Frame::new()
+                .fill(background).stroke(stroke)
+                .inner_margin(10.0)
+                .show(ui, |ui| {
+                    ui.set_width(480.0);
+                    ui.spacing_mut().item_spacing = vec2(20.0, 0.0);
+
                     TableBuilder::new(ui)
-                        .id_salt("Backfit Concentrations")
-                        .min_scrolled_height(height - 20.0)
-                        .max_scroll_height(height - 20.0)
-                        .columns(Column::auto(), 2)
+                        .id_salt("Run Acceptance")
+                        .columns(Column::auto(), 4)
                         .column(Column::remainder())
                         .header(20.0, |mut header| {
-                            header.col(|ui| { ui.add(Label::new("Group").selectable(true)); });
-                            header.col(|ui| { ui.add(Label::new("Raw Corrected").selectable(true)); });
-                            header.col(|ui| { ui.add(Label::new("Backfit").selectable(true)); });
+                            for name in [format!("Standard{}", regression.unit.suffix()), "Backfit".into(), "Recovery %".into(), "%CV".into(), String::new()] {
+                                header.col(|ui| { ui.add(Label::new(name).selectable(true)); });
+                            }
                         })
                         .body(|body| {
-                            body.rows(25.0, unknowns.len(), |mut row| {
-                                let index = row.index();
-                                let (backfit, raw, label) = &unknowns[index];
-
-                                let mut backfit = backfit.to_string();
-                                let mut raw = raw.to_string();
-                                backfit.truncate(10);
-                                raw.truncate(10);
-                                
+                            body.rows(25.0, standards.len(), |mut row| {
+                                let StandardCheck { concentration, backfit, recovery, cv, at_lloq, passed } = standards[row.index()];
+                                for value in [concentration, backfit, recovery, cv] {
+                                    let text = RichText::new(format!("{value:.4}"));
+                                    // Failing standards stand out
+                                    let text = if passed { text } else { text.color(red) };
+                                    row.col(|ui| { ui.add(Label::new(text).selectable(true)); });
+                                }
+                                row.col(|ui| {
+                                    let result = if passed { "Pass" } else { "Fail" };
+                                    let text = if at_lloq { format!("{result} (LLOQ)") } else { result.to_owned() };
+                                    ui.label(if passed { RichText::new(text) } else { RichText::new(text).color(red) });
+                                });
+                            });
+                        });
+
+                    if quality_controls.is_empty() { return }
+                    ui.add_space(10.0);
+                    TableBuilder::new(ui)
+                        .id_salt("Quality Controls")
+                        .columns(Column::auto(), 3)
+                        .column(Column::remainder())
+                        .header(20.0, |mut header| {
+                            for name in ["Quality control", "Limits", "Backfits", ""] {
+                                header.col(|ui| { ui.add(Label::new(name).selectable(true)); });
+                            }
+                        })
+                        .body(|body| {
+                            body.rows(25.0, quality_controls.len(), |mut row| {
+                                let level = &quality_controls[row.index()];
+                                let QualityControlCheck { label, limits: (low, high), backfits, passed, .. } = level;
                                 row.col(|ui| { ui.add(Label::new(label).selectable(true)); });
-                                row.col(|ui| { ui.add(Label::new(raw).selectable(true)); });
-                                row.col(|ui| { ui.add(Label::new(backfit).selectable(true)); });
+                                row.col(|ui| { ui.add(Label::new(format!("{low:.4} - {high:.4}")).selectable(true)); });
+                                row.col(|ui| {
+                                    ui.horizontal(|ui| {
+                                        ui.spacing_mut().item_spacing.x = 8.0;
+                                        for (backfit, &passed) in backfits.iter().zip(passed) {
+                                            let text = RichText::new(format!("{backfit:.4}"));
+                                            ui.add(Label::new(if passed { text } else { text.color(red) }).selectable(true));
+                                        }
+                                    });
+                                });
+                                row.col(|ui| { ui.label(format!("{} of {}", level.passing(), passed.len())); });
+                            });
+                        });
+                });
+        });
+    }
+
+    pub fn relative_potency(&self, ui: &mut Ui) {
+        let Some(regression) = &self.regression else { return };
+        let potencies = &regression.potencies;
+        if potencies.is_empty() { return }
+
+        let background = ui.visuals().faint_bg_color;
+        let stroke = ui.visuals().noninteractive().bg_stroke;
+        let red = Color32::from_hex("#D03030").unwrap();
+
+        egui::Frame::new().show(ui, |ui| {
+            ui.set_width(500.0);
+            ui.vertical_centered(|ui| ui.heading("Relative Potency"));
+            ui.add_space(5.0);
+            ui.vertical_centered(|ui| {
+                ui.label(RichText::new(format!("Parallelism: {}", regression.options.parallelism.name())).size(11.0));
+            });
+            ui.add_space(5.0);
+            egui::Frame::new()
+                .fill(background).stroke(stroke)
+                .inner_margin(10.0)
+                .show(ui, |ui| {
+                    ui.set_width(480.0);
+                    ui.spacing_mut().item_spacing = vec2(20.0, 0.0);
+
+                    TableBuilder::new(ui)
+                        .id_salt("Relative Potency")
+                        .columns(Column::auto(), 6)
+                        .column(Column::remainder())
+                        .header(20.0, |mut header| {
+                            for name in ["Sample", "Potency", "95% CI", "F (p)", "Slope ratio", "Asymptote ratio", ""] {
+                                header.col(|ui| { ui.add(Label::new(name).selectable(true)); });
+                            }
+                        })
+                        .body(|body| {
+                            body.rows(25.0, potencies.len(), |mut row| {
+                                let Potency { label, potency, confidence_interval: (low, high), f, p_value, ratios, parallel, convergence, .. } = &potencies[row.index()];
+                                row.col(|ui| {
+                                    let text = RichText::new(label);
+                                    let text = if convergence.is_converged() { text } else { text.color(red) };
+                                    ui.add(Label::new(text).selectable(true));
+                                });
+                                row.col(|ui| { ui.add(Label::new(format!("{potency:.4}")).selectable(true)); });
+                                row.col(|ui| { ui.add(Label::new(format!("{low:.4} - {high:.4}")).selectable(true)); });
+                                row.col(|ui| { ui.add(Label::new(format!("{f:.2} ({p_value:.4})")).selectable(true)); });
+                                for Ratio { value, confidence_interval: (low, high), .. } in ratios {
+                                    row.col(|ui| { ui.add(Label::new(format!("{value:.3} ({low:.3} - {high:.3})")).selectable(true)); });
+                                }
+                                row.col(|ui| {
+                                    if *parallel {
+                                        ui.label("Parallel");
+                                    } else {
+                                        ui.label(RichText::new("Not parallel").color(red));
+                                    }
+                                });
                             });
                         });
                 });
@@ -265,9 +698,10 @@ impl Elisa {
 
         let Microplate { name, description, .. } = &self.microplate;
         let Some(regression) = &self.regression else { return };
-        let Regression { abcd, unknowns, standards, sse, mse, rmse, sy_x, r_sq,  .. } = regression;
-        let (a, b, c, d) = abcd;
-        let parameters = [("a", a), ("b", b), ("c", c), ("d", d), ("SSE", sse), ("MSE", mse), ("RMSE", rmse), ("Sy.x", sy_x), ("R^2", r_sq)];
+        let Regression { model, unknowns, standards, sse, mse, rmse, sy_x, r_sq, convergence, standard_errors, confidence_intervals, limits, .. } = regression;
+        let mut parameters = regression.parameter_values();
+        parameters.extend([("SSE", *sse), ("MSE", *mse), ("RMSE", *rmse), ("Sy.x", *sy_x), ("R^2", *r_sq)]);
+        parameters.extend([("LOD", limits.lod), ("LLOQ", limits.lloq), ("ULOQ", limits.uloq)]);
 
         let mut pdf = Pdf::new();
 
@@ -309,7 +743,7 @@ impl Elisa {
         content.begin_text();
         content.set_font(font_name, 24.0);
         content.next_line(50.0, 842.0 - 80.0);
-        content.show(Str(b"Assay Analysis - 4PL"));
+        content.show(Str(format!("Assay Analysis - {}", model.name()).as_bytes()));
 
         // Date
         let date_time = chrono::offset::Local::now();
@@ -320,7 +754,11 @@ impl Elisa {
 
         // Name
         content.next_line(0.0, -30.0);
-        content.show(Str(format!("Name: {}", name).as_bytes()));
+        let mut name = format!("Name: {}", name);
+        let Project { name: project, operator, .. } = &self.project;
+        if !project.is_empty() { name += &format!(", Project: {project}") }
+        if !operator.is_empty() { name += &format!(", Operator: {operator}") }
+        content.show(Str(name.as_bytes()));
         content.end_text();
 
         // Image
@@ -348,14 +786,39 @@ impl Elisa {
         // Parameter Table
         content.begin_text();
         content.set_font(font_name, font_size_details);
-        content.next_line(400.0, 842.0 - 175.0);
+        content.next_line(330.0, 842.0 - 175.0);
 
-        for (name, value) in parameters {
+        let truncate = |value: String| value.chars().take(8).collect::<String>();
+        for (i, (name, value)) in parameters.into_iter().enumerate() {
             content.show(Str(name.as_bytes()));
-            content.next_line(40.0, 0.0);
-            content.show(Str(value.to_string().as_bytes()));
-            content.next_line(-40.0, -15.0);
+            content.next_line(30.0, 0.0);
+            content.show(Str(truncate(value.to_string()).as_bytes()));
+            if let (Some(se), Some((low, high))) = (standard_errors.get(i), confidence_intervals.get(i)) {
+                content.next_line(55.0, 0.0);
+                content.show(Str(format!("SE {}", truncate(se.to_string())).as_bytes()));
+                content.next_line(60.0, 0.0);
+                content.show(Str(format!("[{}, {}]", truncate(low.to_string()), truncate(high.to_string())).as_bytes()));
+                content.next_line(-115.0, 0.0);
+            }
+            content.next_line(-30.0, -15.0);
         }
+        content.show(Str(format!("Unit: {}", regression.unit.name()).as_bytes()));
+        content.next_line(0.0, -15.0);
+        content.show(Str(format!("95% CI: {}", regression.options.intervals.name()).as_bytes()));
+        content.next_line(0.0, -15.0);
+        content.show(Str(format!("LOD: {}", regression.options.limits.detection.name()).as_bytes()));
+        content.next_line(0.0, -15.0);
+        content.show(Str(format!("LOQ: {}", regression.options.limits.quantification.name()).as_bytes()));
+        content.next_line(0.0, -15.0);
+        content.show(Str(convergence.description().as_bytes()));
+        if let Some(report) = &regression.multi_start {
+            content.next_line(0.0, -15.0);
+            content.show(Str(report.description().as_bytes()));
+        }
+        content.next_line(0.0, -15.0);
+        content.show(Str(regression.direction_description().as_bytes()));
+        content.next_line(0.0, -15.0);
+        content.show(Str(regression.weighting_description().as_bytes()));
         content.end_text();
     
         // Description
@@ -402,7 +865,7 @@ impl Elisa {
 
         // Calibration table
         let column_width = 75.0;
-        let table_width = column_width * 5.0;
+        let table_width = column_width * 6.0;
 
         content.begin_text();
         content.next_line((a4.x2 - table_width) / 2.0, 842.0 - 585.0);
@@ -410,18 +873,20 @@ impl Elisa {
 
         content.show(Str(b"Standard"));
         content.next_line(column_width, 0.0);
-        content.show(Str(b"Concentration"));
+        content.show(Str(format!("Conc.{}", regression.unit.suffix()).as_bytes()));
         content.next_line(column_width, 0.0);
         content.show(Str(b"Raw Corrected"));
         content.next_line(column_width, 0.0);
         content.show(Str(b"Backfit"));
         content.next_line(column_width, 0.0);
         content.show(Str(b"Recovery %"));
-        content.next_line(-column_width * 4.0, -15.0);
+        content.next_line(column_width, 0.0);
+        content.show(Str(b"Acceptance"));
+        content.next_line(-column_width * 5.0, -15.0);
         
         for (i, (x, y)) in standards.iter().enumerate() {
             let name = format!("Standard {}", i + 1);
-            let backfit = regression.inverse_four_pl(*y);
+            let backfit = regression.inverse(*y);
             let recovery = backfit / x * 100.0;
 
             content.show(Str(name.as_bytes()));
@@ -433,36 +898,116 @@ impl Elisa {
                 content.next_line(column_width, 0.0);
                 content.show(Str(val.as_bytes()));
             }
-            content.next_line(-column_width * 4.0, -15.0);
+            let result = match regression.acceptance.standards.get(i) {
+                Some(StandardCheck { passed: true, .. }) => "Pass",
+                Some(StandardCheck { passed: false, .. }) => "FAIL",
+                None => "",
+            };
+            content.next_line(column_width, 0.0);
+            content.show(Str(result.as_bytes()));
+            content.next_line(-column_width * 5.0, -15.0);
         }    
+        content.show(Str(regression.acceptance.verdict.description().as_bytes()));
+        content.next_line(0.0, -15.0);
+        content.show(Str(format!("Acceptance: {}", regression.acceptance.criteria.description()).as_bytes()));
+        content.next_line(0.0, -15.0);
+        let fields: Vec<_> = self.microplate.read_info.fields().into_iter().map(|(label, value)| format!("{label}: {value}")).collect();
+        if !fields.is_empty() {
+            let line: String = fields.join(", ").chars().filter(char::is_ascii).collect();
+            content.show(Str(line.as_bytes()));
+            content.next_line(0.0, -15.0);
+        }
+        for level in &regression.acceptance.quality_controls {
+            let QualityControlCheck { label, nominal, limits: (low, high), backfits, .. } = level;
+            let backfits: Vec<_> = backfits.iter().map(|backfit| backfit.to_string().chars().take(7).collect::<String>()).collect();
+            let line = format!(
+                "{label} ({} - {}, nominal {nominal}): {}, {} of {} within limits",
+                low.to_string().chars().take(7).collect::<String>(), high.to_string().chars().take(7).collect::<String>(),
+                backfits.join(", "), level.passing(), backfits.len()
+            );
+            content.show(Str(line.as_bytes()));
+            content.next_line(0.0, -15.0);
+        }
+        if !regression.acceptance.quality_controls.is_empty() {
+            content.show(Str(format!("Quality controls: {}", regression.acceptance.criteria.quality_control.name()).as_bytes()));
+            content.next_line(0.0, -15.0);
+        }
 
         content.next_line(0.0, -15.0);
 
-        // Sample Table
+        // Sample Table, wider than the calibration table to make room for the intervals
+        let offsets = [column_width, 55.0, 55.0, 55.0, 45.0, 40.0, 90.0];
+        let sample_table_width = offsets.iter().sum::<f32>() + 90.0;
+        let row_width = offsets.iter().sum::<f32>();
+        content.next_line((table_width - sample_table_width) / 2.0, 0.0);
+
         content.show(Str(b"Sample"));
-        content.next_line(column_width, 0.0);
-        content.show(Str(b"Raw Corrected"));
-        content.next_line(column_width, 0.0);
-        content.show(Str(b"Backfit Concentration"));
-        content.next_line(-column_width * 2.0, -15.0);
+        for (header, offset) in [&b"Raw Corrected"[..], b"Backfit", b"Final", b"SD", b"%CV", b"95% CI", b"95% PI"].into_iter().zip(offsets) {
+            content.next_line(offset, 0.0);
+            content.show(Str(header));
+        }
+        content.next_line(-row_width, -15.0);
 
-        for (i, (x, y, label)) in unknowns.iter().enumerate() {
+        let truncate = |value: &f64, length: usize| value.to_string().chars().take(length).collect::<String>();
+        for (i, unknown) in unknowns.iter().enumerate() {
+            let Unknown { label, raw, backfit, sd, cv, flag, concentration, .. } = unknown;
+            let ((ci_low, ci_high), (pi_low, pi_high)) = unknown.final_intervals();
             let name = if label.is_empty() {
                 format!("Unknown {}", i + 1)
             } else {
                 label.to_owned()
             };
-            let mut raw_corrected = y.to_string();
-            let mut backfit = x.to_string();
-            raw_corrected.truncate(10);
-            backfit.truncate(10);
-            
+
+            let values = match flag {
+                Some(flag) => vec![truncate(raw, 10), flag.label().to_string()],
+                None => vec![
+                    truncate(raw, 9), truncate(backfit, 9), truncate(concentration, 9), truncate(sd, 8), truncate(cv, 6),
+                    format!("{} - {}", truncate(&ci_low, 7), truncate(&ci_high, 7)),
+                    format!("{} - {}", truncate(&pi_low, 7), truncate(&pi_high, 7)),
+                ],
+            };
+            let row_width: f32 = offsets.iter().take(values.len()).sum();
             content.show(Str(name.as_bytes()));
-            content.next_line(column_width, 0.0);
-            content.show(Str(raw_corrected.as_bytes()));
-            content.next_line(column_width, 0.0);
-            content.show(Str(backfit.as_bytes()));
-            content.next_line(-column_width * 2.0, -15.0);
+            for (val, offset) in values.into_iter().zip(offsets) {
+                content.next_line(offset, 0.0);
+                content.show(Str(val.as_bytes()));
+            }
+            content.next_line(-row_width, -15.0);
+        }
+
+        for series in &regression.dilution_series {
+            content.next_line(0.0, -15.0);
+            content.show(Str(series.description().as_bytes()));
+        }
+
+        for Potency { label, potency, confidence_interval: (low, high), f, p_value, ratios, parallel, .. } in &regression.potencies {
+            content.next_line(0.0, -15.0);
+            let parallel = if *parallel { "parallel" } else { "not parallel" };
+            let line = format!("{label}: relative potency {} (95% CI {} - {}), {parallel}", truncate(potency, 7), truncate(low, 7), truncate(high, 7));
+            content.show(Str(line.as_bytes()));
+            content.next_line(0.0, -15.0);
+            let mut line = format!("F = {}, p = {}", truncate(f, 6), truncate(p_value, 6));
+            for Ratio { name, value, confidence_interval: (low, high) } in ratios {
+                line += &format!(", {name} ratio {} ({} - {})", truncate(value, 5), truncate(low, 5), truncate(high, 5));
+            }
+            content.show(Str(line.as_bytes()));
+        }
+        if !regression.potencies.is_empty() {
+            content.next_line(0.0, -15.0);
+            content.show(Str(format!("Parallelism: {}", regression.options.parallelism.name()).as_bytes()));
+            content.next_line(0.0, -15.0);
+        }
+
+        if !regression.excluded.is_empty() {
+            content.next_line(0.0, -15.0);
+            content.show(Str(b"Excluded wells"));
+            content.next_line(0.0, -15.0);
+            for (well, reason) in &regression.excluded {
+                content.show(Str(well.as_bytes()));
+                content.next_line(offsets[0], 0.0);
+                content.show(Str(reason.as_bytes()));
+                content.next_line(-offsets[0], -15.0);
+            }
         }
         
         content.end_text();
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+/// Unit of a concentration. Names stay ASCII, the report fonts cannot show a micro sign.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Unit {
+    #[default]
+    Unspecified,
+    PicogramPerMl,
+    NanogramPerMl,
+    MicrogramPerMl,
+    MilligramPerMl,
+    Picomolar,
+    Nanomolar,
+    Micromolar,
+    Millimolar,
+    InternationalUnitsPerMl,
+    UnitsPerMl,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Dimension {
+    Mass,  // g/L
+    Molar, // mol/L
+    InternationalUnits,
+    Units,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 11] = [
+        Unit::Unspecified,
+        Unit::PicogramPerMl, Unit::NanogramPerMl, Unit::MicrogramPerMl, Unit::MilligramPerMl,
+        Unit::Picomolar, Unit::Nanomolar, Unit::Micromolar, Unit::Millimolar,
+        Unit::InternationalUnitsPerMl, Unit::UnitsPerMl,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Unit::Unspecified => "No unit",
+            Unit::PicogramPerMl => "pg/mL",
+            Unit::NanogramPerMl => "ng/mL",
+            Unit::MicrogramPerMl => "ug/mL",
+            Unit::MilligramPerMl => "mg/mL",
+            Unit::Picomolar => "pM",
+            Unit::Nanomolar => "nM",
+            Unit::Micromolar => "uM",
+            Unit::Millimolar => "mM",
+            Unit::InternationalUnitsPerMl => "IU/mL",
+            Unit::UnitsPerMl => "U/mL",
+        }
+    }
+
+    /// " (ng/mL)" to append to a label, nothing without a unit
+    pub fn suffix(&self) -> String {
+        match self {
+            Unit::Unspecified => String::new(),
+            unit => format!(" ({})", unit.name()),
+        }
+    }
+
+    // Dimension and factor to its base unit
+    fn base(&self) -> Option<(Dimension, f64)> {
+        use Dimension::*;
+        match self {
+            Unit::Unspecified => None,
+            Unit::PicogramPerMl => Some((Mass, 1e-9)),
+            Unit::NanogramPerMl => Some((Mass, 1e-6)),
+            Unit::MicrogramPerMl => Some((Mass, 1e-3)),
+            Unit::MilligramPerMl => Some((Mass, 1.0)),
+            Unit::Picomolar => Some((Molar, 1e-12)),
+            Unit::Nanomolar => Some((Molar, 1e-9)),
+            Unit::Micromolar => Some((Molar, 1e-6)),
+            Unit::Millimolar => Some((Molar, 1e-3)),
+            Unit::InternationalUnitsPerMl => Some((InternationalUnits, 1.0)),
+            Unit::UnitsPerMl => Some((Units, 1.0)),
+        }
+    }
+}
+
+/// Converts between prefixes, and between mass and molar concentrations with the molecular weight in g/mol.
+/// None if the units do not convert into each other.
+pub fn convert(value: f64, from: Unit, to: Unit, molecular_weight: Option<f64>) -> Option<f64> {
+    if from == to { return Some(value) }
+    let (from_dimension, from_factor) = from.base()?;
+    let (to_dimension, to_factor) = to.base()?;
+    let base = value * from_factor;
+
+    let base = match (from_dimension, to_dimension) {
+        (from, to) if from == to => base,
+        (Dimension::Mass, Dimension::Molar) => base / molecular_weight.filter(|&weight| weight > 0.0)?,
+        (Dimension::Molar, Dimension::Mass) => base * molecular_weight.filter(|&weight| weight > 0.0)?,
+        _ => return None,
+    };
+    Some(base / to_factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(value: Option<f64>, expected: f64) -> bool {
+        value.is_some_and(|value| (value - expected).abs() <= expected * 1e-12)
+    }
+
+    #[test]
+    fn converts_prefixes() {
+        assert!(close(convert(1.5, Unit::NanogramPerMl, Unit::PicogramPerMl, None), 1500.0));
+        assert!(close(convert(250.0, Unit::Picomolar, Unit::Nanomolar, None), 0.25));
+    }
+
+    #[test]
+    fn converts_mass_and_molar_with_the_molecular_weight() {
+        // 1 ng/mL of a 50 kDa protein is 20 pM
+        assert!(close(convert(1.0, Unit::NanogramPerMl, Unit::Picomolar, Some(50_000.0)), 20.0));
+        assert!(close(convert(20.0, Unit::Picomolar, Unit::NanogramPerMl, Some(50_000.0)), 1.0));
+        assert_eq!(convert(1.0, Unit::NanogramPerMl, Unit::Picomolar, None), None);
+        assert_eq!(convert(1.0, Unit::NanogramPerMl, Unit::Picomolar, Some(0.0)), None);
+    }
+
+    #[test]
+    fn units_that_do_not_convert() {
+        assert_eq!(convert(1.0, Unit::InternationalUnitsPerMl, Unit::NanogramPerMl, Some(50_000.0)), None);
+        assert_eq!(convert(1.0, Unit::UnitsPerMl, Unit::InternationalUnitsPerMl, None), None);
+        assert_eq!(convert(1.0, Unit::Unspecified, Unit::NanogramPerMl, None), None);
+    }
+
+    #[test]
+    fn same_unit_is_unchanged() {
+        assert_eq!(convert(3.7, Unit::InternationalUnitsPerMl, Unit::InternationalUnitsPerMl, None), Some(3.7));
+        assert_eq!(convert(3.7, Unit::Unspecified, Unit::Unspecified, None), Some(3.7));
+    }
+}